@@ -0,0 +1,34 @@
+//! Looking up the latest NVDA installers from the nvda.zip API, or a mirror of it.
+
+use crate::{
+	checksum::Published,
+	retry::check_status,
+	transport::{Request, Transport},
+};
+use anyhow::Result;
+use serde::Deserialize;
+
+/// The public nvda.zip API.
+pub const DEFAULT_API_BASE: &str = "https://nvda.zip";
+
+/// The response body of an endpoint such as `/stable.json`.
+#[derive(Deserialize)]
+struct Details {
+	url: String,
+	/// The SHA-1 hash of the installer.
+	hash: String,
+	/// The SHA-256 hash of the installer, for mirrors that publish one.
+	#[serde(default)]
+	sha256: Option<String>,
+}
+
+/// Fetches the installer URL and hashes for a channel, e.g. `stable`, from `<base>/<channel>.json`.
+///
+/// # Errors
+///
+/// Fails if the server can't be reached, or doesn't return the details.
+pub async fn get_details(transport: &dyn Transport, base: &str, channel: &str) -> Result<(String, Published)> {
+	let url = format!("{}/{channel}.json", base.trim_end_matches('/'));
+	let details: Details = check_status(transport.send(Request::get(url)).await?)?.json().await?;
+	Ok((details.url, Published { sha1: Some(details.hash), sha256: details.sha256 }))
+}
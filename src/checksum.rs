@@ -0,0 +1,195 @@
+//! Hashing installers with several algorithms at once, and checking them against published hashes.
+
+use clap::ValueEnum;
+use sha1::Sha1;
+use sha2::{Digest, Sha256, Sha512};
+
+/// A hash algorithm.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Algo {
+	/// SHA-1, as published by the nvda.zip API.
+	Sha1,
+	/// SHA-256.
+	Sha256,
+	/// SHA-512, which is only computed when asked for.
+	Sha512,
+}
+
+impl Algo {
+	#[must_use]
+	pub const fn name(self) -> &'static str {
+		match self {
+			Self::Sha1 => "SHA-1",
+			Self::Sha256 => "SHA-256",
+			Self::Sha512 => "SHA-512",
+		}
+	}
+}
+
+/// Hashes bytes with every algorithm at once, so an installer only has to be read once.
+pub struct Hasher {
+	sha1: Sha1,
+	sha256: Sha256,
+	sha512: Option<Sha512>,
+}
+
+impl Hasher {
+	/// A hasher for SHA-1 and SHA-256, and SHA-512 if asked for.
+	#[must_use]
+	pub fn new(sha512: bool) -> Self {
+		Self { sha1: Sha1::new(), sha256: Sha256::new(), sha512: sha512.then(Sha512::new) }
+	}
+
+	pub fn update(&mut self, bytes: &[u8]) {
+		self.sha1.update(bytes);
+		self.sha256.update(bytes);
+		if let Some(sha512) = &mut self.sha512 {
+			sha512.update(bytes);
+		}
+	}
+
+	#[must_use]
+	pub fn finalize(self) -> Digests {
+		Digests {
+			sha1: self.sha1.finalize().into(),
+			sha256: self.sha256.finalize().into(),
+			sha512: self.sha512.map(|sha512| sha512.finalize().into()),
+		}
+	}
+}
+
+/// The hashes of an installer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Digests {
+	pub sha1: [u8; 20],
+	pub sha256: [u8; 32],
+	pub sha512: Option<[u8; 64]>,
+}
+
+impl Digests {
+	/// The hash for an algorithm in hex, if it was computed.
+	#[must_use]
+	pub fn hex(&self, algo: Algo) -> Option<String> {
+		match algo {
+			Algo::Sha1 => Some(base16ct::lower::encode_string(&self.sha1)),
+			Algo::Sha256 => Some(base16ct::lower::encode_string(&self.sha256)),
+			Algo::Sha512 => self.sha512.map(|sha512| base16ct::lower::encode_string(&sha512)),
+		}
+	}
+
+	/// Describes the first expected hash these don't match, such as `SHA-256 ab12..., not cd34...`.
+	#[must_use]
+	pub fn mismatch(&self, expected: &Expected) -> Option<String> {
+		let describe = |algo: Algo, actual: &[u8], expected: &[u8]| {
+			(actual != expected).then(|| {
+				let (actual, expected) =
+					(base16ct::lower::encode_string(actual), base16ct::lower::encode_string(expected));
+				format!("{} {actual}, not {expected}", algo.name())
+			})
+		};
+		expected
+			.sha256
+			.and_then(|sha256| describe(Algo::Sha256, &self.sha256, &sha256))
+			.or_else(|| expected.sha1.and_then(|sha1| describe(Algo::Sha1, &self.sha1, &sha1)))
+	}
+}
+
+/// The hashes an installer should have, in hex, straight from the API, a lockfile or the command line.
+#[derive(Clone, Default, Debug, PartialEq, Eq)]
+pub struct Published {
+	pub sha1: Option<String>,
+	pub sha256: Option<String>,
+}
+
+impl Published {
+	/// Whether no hashes are published at all.
+	#[must_use]
+	pub const fn is_empty(&self) -> bool {
+		self.sha1.is_none() && self.sha256.is_none()
+	}
+
+	/// The published hash for an algorithm, if there is one.
+	#[must_use]
+	pub fn get(&self, algo: Algo) -> Option<&str> {
+		match algo {
+			Algo::Sha1 => self.sha1.as_deref(),
+			Algo::Sha256 => self.sha256.as_deref(),
+			Algo::Sha512 => None,
+		}
+	}
+
+	/// Decodes the hashes, or returns `None` if any of them isn't valid.
+	#[must_use]
+	pub fn decode(&self) -> Option<Expected> {
+		let expected =
+			Expected { sha1: self.sha1.as_deref().and_then(decode), sha256: self.sha256.as_deref().and_then(decode) };
+		(expected.sha1.is_some() == self.sha1.is_some() && expected.sha256.is_some() == self.sha256.is_some())
+			.then_some(expected)
+	}
+}
+
+/// The decoded hashes an installer should have.
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub struct Expected {
+	pub sha1: Option<[u8; 20]>,
+	pub sha256: Option<[u8; 32]>,
+}
+
+impl Expected {
+	#[must_use]
+	pub const fn is_empty(&self) -> bool {
+		self.sha1.is_none() && self.sha256.is_none()
+	}
+}
+
+/// Decodes a hex hash of a particular length, in either case.
+#[must_use]
+pub fn decode<const N: usize>(hex: &str) -> Option<[u8; N]> {
+	let mut hash = [0u8; N];
+	(base16ct::mixed::decode(hex, &mut hash).ok()?.len() == N).then_some(hash)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const SHA1_ABC: &str = "a9993e364706816aba3e25717850c26c9cd0d89d";
+	const SHA256_ABC: &str = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+
+	fn abc() -> Digests {
+		let mut hasher = Hasher::new(false);
+		hasher.update(b"a");
+		hasher.update(b"bc");
+		hasher.finalize()
+	}
+
+	#[test]
+	fn hashes_with_every_algorithm() {
+		let digests = abc();
+		assert_eq!(digests.hex(Algo::Sha1).as_deref(), Some(SHA1_ABC));
+		assert_eq!(digests.hex(Algo::Sha256).as_deref(), Some(SHA256_ABC));
+		assert_eq!(digests.hex(Algo::Sha512), None);
+		assert!(Hasher::new(true).finalize().sha512.is_some());
+	}
+
+	#[test]
+	fn checks_published_hashes() {
+		let published = |sha1: Option<&str>, sha256: Option<&str>| Published {
+			sha1: sha1.map(str::to_owned),
+			sha256: sha256.map(str::to_owned),
+		};
+		let wrong = "0".repeat(64);
+		let cases = [
+			(published(None, None), Some(true)),
+			(published(Some(SHA1_ABC), None), Some(true)),
+			(published(Some(&SHA1_ABC.to_uppercase()), Some(SHA256_ABC)), Some(true)),
+			(published(Some(SHA1_ABC), Some(&wrong)), Some(false)),
+			(published(None, Some(&wrong[..40])), None),
+			(published(Some("not hex"), Some(SHA256_ABC)), None),
+		];
+		for (published, expected) in cases {
+			let matches = published.decode().map(|expected| abc().mismatch(&expected).is_none());
+			assert_eq!(matches, expected, "checking against {published:?}");
+		}
+	}
+}
@@ -0,0 +1,800 @@
+//! Streaming installer downloads to disk, resuming interrupted transfers where the server allows it.
+//!
+//! While a download is in flight the bytes live in `<name>.part`, next to a small `<name>.part.json` sidecar recording
+//! where they came from. If the transfer is cut short, the next run for the same URL and hash picks up from the end of
+//! the partial file with a `Range` request, guarded by `If-Range` so a changed file on the server restarts from zero.
+//!
+//! Downloads can also be split into segments: byte ranges fetched at the same time over separate connections and written
+//! into place in the `.part` file, which is hashed once they're all done. Segmented downloads aren't resumed, and fall
+//! back to a single stream if the server doesn't support ranges.
+
+use crate::{
+	checksum::{Digests, Hasher},
+	resolve::Installer,
+	retry::{Retry, check_status, is_transient},
+	transport::{ConnectionError, Request, Response, Transport},
+};
+use anyhow::{Context, Result, bail};
+use reqwest::{
+	StatusCode,
+	header::{ACCEPT_RANGES, CONTENT_RANGE, ETAG, IF_RANGE, LAST_MODIFIED, RANGE},
+};
+use serde::{Deserialize, Serialize};
+use std::{
+	ffi::OsString,
+	fmt,
+	io::{SeekFrom, Write},
+	path::{Path, PathBuf},
+	sync::{
+		Arc, Mutex, PoisonError,
+		atomic::{AtomicU64, Ordering},
+	},
+	time::Duration,
+};
+use tokio::{
+	fs,
+	io::{AsyncReadExt, AsyncSeekExt, AsyncWrite, AsyncWriteExt},
+	task::JoinSet,
+	time::Instant,
+};
+
+/// Something that reports on the progress of downloads, such as a progress bar.
+pub trait Progress: Send + Sync {
+	/// Starts reporting on a transfer of `total` bytes (if known), of which `done` are already on disk.
+	fn start(&self, total: Option<u64>, done: u64) -> Box<dyn Transfer>;
+
+	/// Tells the user about something that happened along the way. Ignored by default.
+	fn event(&self, _event: &Event<'_>) {}
+}
+
+/// Something worth telling the user about while looking up or downloading an installer. Displays as a sentence.
+#[derive(Debug)]
+pub enum Event<'a> {
+	/// An operation failed, and will be tried again after `delay`.
+	Retrying { what: &'a str, error: &'a anyhow::Error, delay: Duration, attempt: u32, retries: u32 },
+	/// A download failed, but what arrived so far was kept to resume from next time.
+	PartialKept,
+	/// A partial download is being resumed from this byte.
+	Resuming(u64),
+	/// The server sent the whole file instead of the rest of it, so the download started again.
+	Restarting,
+	/// The server ignored the ranges of a segmented download, so it's being downloaded in a single stream.
+	RangesIgnored,
+	/// An installer is being downloaded without saving it, to compute the hashes that aren't published.
+	ComputingDigests,
+	/// The hashes published for a channel couldn't be looked up, so they can't be used to verify a release.
+	HashLookupFailed { channel: &'a str, error: &'a anyhow::Error },
+}
+
+impl fmt::Display for Event<'_> {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::Retrying { what, error, delay, attempt, retries } => write!(
+				f,
+				"{what} failed: {}. Retrying in {:.1} seconds (retry {attempt} of {retries})...",
+				error.to_string().trim_end_matches('.'),
+				delay.as_secs_f64()
+			),
+			Self::PartialKept => f.write_str("The partial download has been kept, and will be resumed next time."),
+			Self::Resuming(from) => write!(f, "Resuming previous download from byte {from}..."),
+			Self::Restarting => {
+				f.write_str("The server did not resume the download, starting again from the beginning.")
+			}
+			Self::RangesIgnored => {
+				f.write_str("The server did not honour range requests, downloading in a single stream instead.")
+			}
+			Self::ComputingDigests => {
+				f.write_str("Not every hash is published for this version, so downloading it to compute them...")
+			}
+			Self::HashLookupFailed { channel, error } => write!(
+				f,
+				"Couldn't look up the hashes published for {channel}, so they can't be used to verify this release: {}.",
+				error.to_string().trim_end_matches('.')
+			),
+		}
+	}
+}
+
+/// Reports on the progress of a single transfer.
+pub trait Transfer: Send {
+	/// Records that `n` more bytes have been received.
+	fn advance(&mut self, n: u64);
+	/// Reports the final state of the transfer.
+	fn finish(self: Box<Self>);
+}
+
+/// Doesn't report progress at all.
+pub struct NoProgress;
+
+impl Progress for NoProgress {
+	fn start(&self, _total: Option<u64>, _done: u64) -> Box<dyn Transfer> {
+		Box::new(Self)
+	}
+}
+
+impl Transfer for NoProgress {
+	fn advance(&mut self, _n: u64) {}
+
+	fn finish(self: Box<Self>) {}
+}
+
+/// Downloads installers, retrying failed requests, resuming interrupted transfers and hashing the bytes as they arrive.
+#[derive(Clone)]
+pub struct Downloader {
+	transport: Arc<dyn Transport>,
+	retry: Retry,
+	progress: Arc<dyn Progress>,
+	sha512: bool,
+	limit_rate: Option<u64>,
+	segments: u64,
+}
+
+impl Downloader {
+	/// A downloader that retries failed requests the default number of times, and doesn't report progress.
+	#[must_use]
+	pub fn new(transport: Arc<dyn Transport>) -> Self {
+		Self {
+			transport,
+			retry: Retry::default(),
+			progress: Arc::new(NoProgress),
+			sha512: false,
+			limit_rate: None,
+			segments: 1,
+		}
+	}
+
+	#[must_use]
+	pub const fn retry(mut self, retry: Retry) -> Self {
+		self.retry = retry;
+		self
+	}
+
+	#[must_use]
+	pub fn progress(mut self, progress: impl Progress + 'static) -> Self {
+		self.progress = Arc::new(progress);
+		self
+	}
+
+	/// Also computes SHA-512 hashes, which are skipped by default.
+	#[must_use]
+	pub const fn sha512(mut self, sha512: bool) -> Self {
+		self.sha512 = sha512;
+		self
+	}
+
+	/// Receives installers no faster than this many bytes per second. `None` doesn't limit them.
+	#[must_use]
+	pub const fn limit_rate(mut self, bytes_per_second: Option<u64>) -> Self {
+		self.limit_rate = bytes_per_second;
+		self
+	}
+
+	/// Downloads installers in this many byte ranges at once, if the server supports ranges. 1, the default, downloads
+	/// them in a single stream.
+	#[must_use]
+	pub const fn segments(mut self, segments: u64) -> Self {
+		self.segments = segments;
+		self
+	}
+
+	/// Downloads an installer with the intention of saving it to `dest`, resuming a previous partial download if one
+	/// matches. The caller decides what to do with it once it has checked the hashes.
+	///
+	/// # Errors
+	///
+	/// Fails if the download fails, even after retrying.
+	pub async fn download(&self, installer: &Installer, dest: &Path) -> Result<Download> {
+		let published = &installer.published;
+		let hash = published.sha256.as_deref().or(published.sha1.as_deref()).unwrap_or_default();
+		let resumable = load_state(&with_suffix(dest, ".part.json"))
+			.await
+			.is_some_and(|state| state.url == installer.url && state.hash == hash);
+		if self.segments > 1
+			&& !resumable
+			&& let Some(ranges) = self.probe_ranges(&installer.url).await
+		{
+			match fetch_segmented(self, &installer.url, dest, ranges).await {
+				Err(err) if err.is::<RangesIgnored>() => self.progress.event(&Event::RangesIgnored),
+				result => return result,
+			}
+		}
+		self.retry.run("Download", &*self.progress, || fetch(self, &installer.url, hash, dest)).await
+	}
+
+	/// Downloads an installer straight to a writer such as standard output, returning the hashes of what was written.
+	///
+	/// # Errors
+	///
+	/// Fails if the download fails. Only the request is retried, as some of the installer may already have been
+	/// written.
+	pub async fn stream(&self, url: &str, writer: impl AsyncWrite + Unpin) -> Result<Digests> {
+		stream(self, self.get(url).await?, writer).await
+	}
+
+	/// Downloads an installer without saving it, just to find out its hashes.
+	///
+	/// # Errors
+	///
+	/// Fails if the download fails.
+	pub async fn digests(&self, url: &str) -> Result<Digests> {
+		self.progress.event(&Event::ComputingDigests);
+		self.stream(url, tokio::io::sink()).await
+	}
+
+	/// Computes the hashes of a file on disk.
+	///
+	/// # Errors
+	///
+	/// Fails if the file can't be read.
+	pub async fn hash_file(&self, path: &Path) -> Result<Digests> {
+		hash_file(path, self.hasher()).await
+	}
+
+	fn hasher(&self) -> Hasher {
+		Hasher::new(self.sha512)
+	}
+
+	/// Asks the server whether a URL can be downloaded in ranges, and if so, how to split it up. `None` if it can't be,
+	/// or isn't big enough to be worth splitting.
+	async fn probe_ranges(&self, url: &str) -> Option<Ranges> {
+		let response = check_status(self.transport.send(Request::head(url)).await.ok()?).ok()?;
+		if response.header(ACCEPT_RANGES) != Some("bytes") {
+			return None;
+		}
+		let len = response.content_length()?;
+		let segments = self.segments.min(len / MIN_SEGMENT);
+		let validator = ResumeState::from_response(url, "", &response).validator().map(str::to_owned);
+		(segments > 1).then_some(Ranges { len, segments, validator })
+	}
+
+	/// Sends a GET request, retrying until the server starts sending a successful response.
+	async fn get(&self, url: &str) -> Result<Response> {
+		self.retry
+			.run("Download", &*self.progress, || async {
+				Ok(check_status(self.transport.send(Request::get(url)).await?)?)
+			})
+			.await
+	}
+}
+
+/// What we remember about a partial download so it can be resumed safely.
+#[derive(Serialize, Deserialize, PartialEq, Eq)]
+struct ResumeState {
+	url: String,
+	hash: String,
+	etag: Option<String>,
+	last_modified: Option<String>,
+}
+
+impl ResumeState {
+	fn from_response(url: &str, hash: &str, response: &Response) -> Self {
+		let header = |name| response.header(name).map(str::to_owned);
+		Self { url: url.to_owned(), hash: hash.to_owned(), etag: header(ETAG), last_modified: header(LAST_MODIFIED) }
+	}
+
+	/// The validator to send in `If-Range`. Strong `ETag`s are preferred over `Last-Modified`.
+	fn validator(&self) -> Option<&str> {
+		self.etag.as_deref().filter(|etag| !etag.starts_with("W/")).or(self.last_modified.as_deref())
+	}
+}
+
+/// A completed download sitting in its `.part` file, waiting to be kept or thrown away.
+pub struct Download {
+	part: PathBuf,
+	/// The hashes of the downloaded bytes.
+	pub digests: Digests,
+}
+
+impl Download {
+	/// Moves the downloaded file to its final location, replacing whatever was there in one step.
+	///
+	/// # Errors
+	///
+	/// Fails if the file can't be moved, in which case it's deleted.
+	pub async fn persist(self, dest: &Path) -> Result<()> {
+		if let Err(err) = fs::rename(&self.part, dest).await {
+			let _ = fs::remove_file(&self.part).await;
+			return Err(err).with_context(|| format!("Failed to move download to {}.", dest.display()));
+		}
+		// Make sure the rename itself survives a crash.
+		#[cfg(unix)]
+		if let Some(dir) = dest.parent().map(|dir| if dir.as_os_str().is_empty() { Path::new(".") } else { dir })
+			&& let Ok(dir) = fs::File::open(dir).await
+		{
+			let _ = dir.sync_all().await;
+		}
+		Ok(())
+	}
+
+	/// Deletes the downloaded file.
+	///
+	/// # Errors
+	///
+	/// Fails if the file can't be deleted.
+	pub async fn discard(self) -> Result<()> {
+		Ok(fs::remove_file(&self.part).await?)
+	}
+}
+
+/// Downloads `url` with the intention of saving it to `dest`, resuming a previous partial download if one matches.
+///
+/// If the download fails, the partial file is kept only if the failure looks temporary and the server lets us resume.
+async fn fetch(downloader: &Downloader, url: &str, hash: &str, dest: &Path) -> Result<Download> {
+	let part = with_suffix(dest, ".part");
+	let sidecar = with_suffix(dest, ".part.json");
+	let result = fetch_to(downloader, &part, &sidecar, url, hash).await;
+	if let Err(err) = &result {
+		if is_transient(err) && fs::try_exists(&sidecar).await.unwrap_or(false) {
+			downloader.progress.event(&Event::PartialKept);
+		} else {
+			clean_up(dest).await;
+		}
+	}
+	result
+}
+
+/// Removes any partial download for `dest`.
+pub async fn clean_up(dest: &Path) {
+	let _ = fs::remove_file(with_suffix(dest, ".part")).await;
+	let _ = fs::remove_file(with_suffix(dest, ".part.json")).await;
+}
+
+async fn fetch_to(downloader: &Downloader, part: &Path, sidecar: &Path, url: &str, hash: &str) -> Result<Download> {
+	let (transport, mut hasher) = (&downloader.transport, downloader.hasher());
+	let mut offset = 0;
+	let mut request = Request::get(url);
+	if let Some(state) = load_state(sidecar).await.filter(|state| state.url == url && state.hash == hash) {
+		let len = fs::metadata(part).await.map_or(0, |m| m.len());
+		if let Some(validator) = state.validator().filter(|_| len > 0) {
+			downloader.progress.event(&Event::Resuming(len));
+			request = request.header(RANGE, &format!("bytes={len}-")).header(IF_RANGE, validator);
+			offset = len;
+		}
+	}
+	let mut response = transport.send(request).await?;
+	let resumed =
+		offset > 0 && response.status == StatusCode::PARTIAL_CONTENT && range_start(&response) == Some(offset);
+	if offset > 0 && !resumed {
+		downloader.progress.event(&Event::Restarting);
+		if response.status != StatusCode::OK {
+			response = transport.send(Request::get(url)).await?;
+		}
+	}
+	let response = check_status(response)?;
+	let state = ResumeState::from_response(url, hash, &response);
+	if state.validator().is_some() {
+		fs::write(sidecar, serde_json::to_vec(&state)?).await?;
+	} else {
+		let _ = fs::remove_file(sidecar).await;
+	}
+	let mut file = if resumed {
+		hash_existing(part, &mut hasher).await?;
+		fs::OpenOptions::new().append(true).open(part).await?
+	} else {
+		fs::File::create(part).await?
+	};
+	let done = if resumed { offset } else { 0 };
+	let mut transfer = downloader.progress.start(response.content_length().map(|len| len + done), done);
+	let mut throttle = Throttle::new(downloader.limit_rate);
+	write_body(response, &mut file, &mut hasher, &mut *transfer, &mut throttle)
+		.await
+		.context("The download was interrupted.")?;
+	transfer.finish();
+	file.sync_data().await?;
+	drop(file);
+	let _ = fs::remove_file(sidecar).await;
+	Ok(Download { part: part.to_owned(), digests: hasher.finalize() })
+}
+
+/// Segments are never smaller than this, so small files aren't split into lots of tiny requests.
+const MIN_SEGMENT: u64 = 16 * 1024;
+
+/// How to split a download into ranges.
+struct Ranges {
+	len: u64,
+	segments: u64,
+	/// What to send in `If-Range`, so every segment comes from the same version of the file.
+	validator: Option<String>,
+}
+
+/// A server answered a range request with something other than the range, so segments can't be used.
+#[derive(Debug, thiserror::Error)]
+#[error("The server ignored a range request.")]
+struct RangesIgnored;
+
+/// A byte range of a segmented download, and how much of it has been written.
+struct Segment {
+	start: u64,
+	end: u64,
+	written: AtomicU64,
+}
+
+/// What the segments of a download share.
+struct Segmented {
+	downloader: Downloader,
+	url: String,
+	part: PathBuf,
+	validator: Option<String>,
+	transfer: Mutex<Box<dyn Transfer>>,
+	/// The rate limit for each segment, so that together they keep to the downloader's.
+	limit_rate: Option<u64>,
+}
+
+/// Downloads `url` in segments into the `.part` file for `dest`, then hashes it. Any partial download is deleted if
+/// it fails.
+async fn fetch_segmented(downloader: &Downloader, url: &str, dest: &Path, ranges: Ranges) -> Result<Download> {
+	let part = with_suffix(dest, ".part");
+	let result = fetch_segments(downloader, url, &part, ranges).await;
+	if result.is_err() {
+		clean_up(dest).await;
+	}
+	result
+}
+
+async fn fetch_segments(downloader: &Downloader, url: &str, part: &Path, ranges: Ranges) -> Result<Download> {
+	let _ = fs::remove_file(with_suffix(part, ".json")).await;
+	fs::File::create(part).await?.set_len(ranges.len).await?;
+	let shared = Arc::new(Segmented {
+		downloader: downloader.clone(),
+		url: url.to_owned(),
+		part: part.to_owned(),
+		validator: ranges.validator,
+		transfer: Mutex::new(downloader.progress.start(Some(ranges.len), 0)),
+		limit_rate: downloader.limit_rate.map(|rate| (rate / ranges.segments).max(1)),
+	});
+	let size = ranges.len.div_ceil(ranges.segments);
+	let mut tasks = JoinSet::new();
+	for start in (0..ranges.len).step_by(usize::try_from(size)?) {
+		let segment = Segment { start, end: (start + size).min(ranges.len) - 1, written: AtomicU64::new(0) };
+		let shared = shared.clone();
+		tasks.spawn(async move {
+			let retry = shared.downloader.retry;
+			retry.run("Download", &*shared.downloader.progress, || shared.fetch(&segment)).await
+		});
+	}
+	while let Some(result) = tasks.join_next().await {
+		result?.context("The download was interrupted.")?;
+	}
+	if let Some(shared) = Arc::into_inner(shared) {
+		shared.transfer.into_inner().unwrap_or_else(PoisonError::into_inner).finish();
+	}
+	Ok(Download { part: part.to_owned(), digests: hash_file(part, downloader.hasher()).await? })
+}
+
+impl Segmented {
+	/// Fetches the rest of a segment and writes it into place, picking up where an earlier attempt left off.
+	async fn fetch(&self, segment: &Segment) -> Result<()> {
+		let from = segment.start + segment.written.load(Ordering::Relaxed);
+		if from > segment.end {
+			return Ok(());
+		}
+		let mut request = Request::get(&self.url).header(RANGE, &format!("bytes={from}-{}", segment.end));
+		if let Some(validator) = &self.validator {
+			request = request.header(IF_RANGE, validator);
+		}
+		let mut response = check_status(self.downloader.transport.send(request).await?)?;
+		if response.status != StatusCode::PARTIAL_CONTENT || range_start(&response) != Some(from) {
+			bail!(RangesIgnored);
+		}
+		let mut file = fs::OpenOptions::new().write(true).open(&self.part).await?;
+		file.seek(SeekFrom::Start(from)).await?;
+		let mut throttle = Throttle::new(self.limit_rate);
+		while let Some(chunk) = response.chunk().await? {
+			let left = segment.end + 1 - segment.start - segment.written.load(Ordering::Relaxed);
+			let chunk = &chunk[..chunk.len().min(usize::try_from(left)?)];
+			file.write_all(chunk).await?;
+			segment.written.fetch_add(chunk.len() as u64, Ordering::Relaxed);
+			self.transfer.lock().unwrap_or_else(PoisonError::into_inner).advance(chunk.len() as u64);
+			throttle.pace(chunk.len() as u64).await;
+		}
+		file.sync_data().await?;
+		if segment.start + segment.written.load(Ordering::Relaxed) <= segment.end {
+			bail!(ConnectionError("the connection closed before the whole range arrived".to_owned()));
+		}
+		Ok(())
+	}
+}
+
+/// Streams a response body straight to a writer such as standard output, returning the hashes of what was written.
+async fn stream(downloader: &Downloader, response: Response, mut writer: impl AsyncWrite + Unpin) -> Result<Digests> {
+	let mut hasher = downloader.hasher();
+	let mut transfer = downloader.progress.start(response.content_length(), 0);
+	write_body(response, &mut writer, &mut hasher, &mut *transfer, &mut Throttle::new(downloader.limit_rate)).await?;
+	transfer.finish();
+	writer.flush().await?;
+	Ok(hasher.finalize())
+}
+
+/// Streams a response body into a writer chunk by chunk, feeding every chunk to the hasher as it goes.
+async fn write_body(
+	mut response: Response,
+	file: &mut (impl AsyncWrite + Unpin),
+	hasher: &mut Hasher,
+	transfer: &mut dyn Transfer,
+	throttle: &mut Throttle,
+) -> Result<()> {
+	while let Some(chunk) = response.chunk().await? {
+		hasher.update(&chunk);
+		file.write_all(&chunk).await?;
+		transfer.advance(chunk.len() as u64);
+		throttle.pace(chunk.len() as u64).await;
+	}
+	Ok(())
+}
+
+/// Keeps a transfer under a rate limit by sleeping whenever it gets ahead of it.
+struct Throttle {
+	bytes_per_second: Option<u64>,
+	start: Instant,
+	received: u64,
+}
+
+impl Throttle {
+	fn new(bytes_per_second: Option<u64>) -> Self {
+		Self { bytes_per_second, start: Instant::now(), received: 0 }
+	}
+
+	/// Records that `n` more bytes have arrived, and waits until the limit allows for them.
+	async fn pace(&mut self, n: u64) {
+		let Some(rate) = self.bytes_per_second.filter(|&rate| rate > 0) else {
+			return;
+		};
+		self.received += n;
+		let nanos = u128::from(self.received) * 1_000_000_000 / u128::from(rate);
+		tokio::time::sleep_until(self.start + Duration::from_nanos(u64::try_from(nanos).unwrap_or(u64::MAX))).await;
+	}
+}
+
+/// Parses a rate such as `500K` or `2M` into bytes per second. The suffixes are powers of 1024, as in curl.
+///
+/// # Errors
+///
+/// Returns a description of what was expected if the rate isn't a positive number with an optional suffix.
+pub fn parse_rate(s: &str) -> Result<u64, String> {
+	let error = || format!("expected a number of bytes per second such as 500K or 2M, not {s}");
+	let s = s.trim();
+	let (number, multiplier) = match s.char_indices().last() {
+		Some((i, 'k' | 'K')) => (&s[..i], 1u64 << 10),
+		Some((i, 'm' | 'M')) => (&s[..i], 1 << 20),
+		Some((i, 'g' | 'G')) => (&s[..i], 1 << 30),
+		_ => (s, 1),
+	};
+	let number: f64 = number.parse().map_err(|_| error())?;
+	#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss, clippy::cast_precision_loss)]
+	let rate = (number * multiplier as f64) as u64;
+	if number.is_finite() && rate > 0 { Ok(rate) } else { Err(error()) }
+}
+
+/// Computes the hashes of a file on disk.
+///
+/// # Errors
+///
+/// Fails if the file can't be read.
+pub async fn hash_file(path: &Path, mut hasher: Hasher) -> Result<Digests> {
+	hash_existing(path, &mut hasher).await?;
+	Ok(hasher.finalize())
+}
+
+/// Feeds the bytes of a file, such as a partial download, to the hasher.
+async fn hash_existing(path: &Path, hasher: &mut Hasher) -> Result<()> {
+	let mut file = fs::File::open(path).await?;
+	let mut buf = vec![0; 64 * 1024];
+	loop {
+		let n = file.read(&mut buf).await?;
+		if n == 0 {
+			return Ok(());
+		}
+		hasher.update(&buf[..n]);
+	}
+}
+
+async fn load_state(sidecar: &Path) -> Option<ResumeState> {
+	serde_json::from_slice(&fs::read(sidecar).await.ok()?).ok()
+}
+
+/// Parses the first byte position out of a `Content-Range: bytes <start>-<end>/<len>` header.
+fn range_start(response: &Response) -> Option<u64> {
+	let value = response.header(CONTENT_RANGE)?;
+	value.strip_prefix("bytes ")?.split('-').next()?.trim().parse().ok()
+}
+
+/// Writes a small file such as a lockfile or checksum manifest by writing `<path>.tmp` and renaming it over `path`, so a
+/// crash or failed write leaves the old file intact.
+pub(crate) fn write_replacing(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+	let temp = with_suffix(path, ".tmp");
+	let result = std::fs::File::create(&temp)
+		.and_then(|mut file| {
+			file.write_all(contents)?;
+			file.sync_all()
+		})
+		.and_then(|()| std::fs::rename(&temp, path));
+	if result.is_err() {
+		let _ = std::fs::remove_file(&temp);
+	}
+	result
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+	let mut name = OsString::from(path.as_os_str());
+	name.push(suffix);
+	name.into()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{
+		testing::{Events, URL, body, installer, temp_dir},
+		transport::{FakeTransport, Fault},
+	};
+	use reqwest::{Method, StatusCode};
+	use std::time::Duration;
+
+	fn downloader(fake: &Arc<FakeTransport>, retries: u32) -> Downloader {
+		Downloader::new(fake.clone()).retry(Retry::new(retries, Duration::ZERO, Duration::ZERO))
+	}
+
+	/// A destination in a fresh temporary directory.
+	fn dest() -> PathBuf {
+		temp_dir().join("nvda.exe")
+	}
+
+	#[tokio::test]
+	async fn retries_server_errors() {
+		let fake = Arc::new(
+			FakeTransport::new()
+				.serve(URL, body())
+				.fail(URL, Fault::Status(StatusCode::SERVICE_UNAVAILABLE))
+				.fail(URL, Fault::Refuse),
+		);
+		let installer = installer(&body());
+		let dest = dest();
+		let events = Events::default();
+		let download = downloader(&fake, 2).progress(events.clone()).download(&installer, &dest).await.unwrap();
+		assert_eq!(download.digests.mismatch(&installer.published.decode().unwrap()), None);
+		assert_eq!(fake.requests().len(), 3);
+		let events = events.take();
+		assert_eq!(events.len(), 2);
+		assert!(events[0].starts_with("Download failed: the server returned 503"), "{events:?}");
+		assert!(events[1].ends_with("Retrying in 0.0 seconds (retry 2 of 2)..."), "{events:?}");
+		download.persist(&dest).await.unwrap();
+		assert_eq!(std::fs::read(&dest).unwrap(), body());
+
+		let fake = Arc::new(FakeTransport::new().serve(URL, body()).fail(URL, Fault::Status(StatusCode::FORBIDDEN)));
+		assert!(downloader(&fake, 2).download(&installer, &dest).await.is_err());
+		assert_eq!(fake.requests().len(), 1, "client errors shouldn't be retried");
+		std::fs::remove_dir_all(dest.parent().unwrap()).unwrap();
+	}
+
+	#[tokio::test]
+	async fn resumes_interrupted_downloads() {
+		let fake =
+			Arc::new(FakeTransport::new().serve_resumable(URL, body(), "\"v1\"").fail(URL, Fault::Disconnect(40_000)));
+		let installer = installer(&body());
+		let dest = dest();
+		let events = Events::default();
+		assert!(downloader(&fake, 0).progress(events.clone()).download(&installer, &dest).await.is_err());
+		assert_eq!(std::fs::metadata(with_suffix(&dest, ".part")).unwrap().len(), 40_000);
+		assert_eq!(events.take(), [Event::PartialKept.to_string()]);
+
+		let download = downloader(&fake, 0).progress(events.clone()).download(&installer, &dest).await.unwrap();
+		assert_eq!(events.take(), [Event::Resuming(40_000).to_string()]);
+		assert_eq!(download.digests.mismatch(&installer.published.decode().unwrap()), None);
+		let resumed = &fake.requests()[1];
+		assert_eq!((&resumed.method, resumed.header_str(RANGE)), (&Method::GET, Some("bytes=40000-")));
+		assert_eq!(resumed.header_str(IF_RANGE), Some("\"v1\""));
+		download.persist(&dest).await.unwrap();
+		assert_eq!(std::fs::read(&dest).unwrap(), body());
+		assert!(!fs::try_exists(with_suffix(&dest, ".part.json")).await.unwrap());
+		std::fs::remove_dir_all(dest.parent().unwrap()).unwrap();
+	}
+
+	#[tokio::test]
+	async fn detects_hash_mismatches() {
+		let mut tampered = body();
+		tampered[50_000] ^= 1;
+		let fake = Arc::new(FakeTransport::new().serve(URL, tampered));
+		let installer = installer(&body());
+		let dest = dest();
+		let download = downloader(&fake, 0).download(&installer, &dest).await.unwrap();
+		let mismatch = download.digests.mismatch(&installer.published.decode().unwrap());
+		assert!(mismatch.as_deref().is_some_and(|mismatch| mismatch.starts_with("SHA-256 ")), "{mismatch:?}");
+		download.discard().await.unwrap();
+		assert!(!fs::try_exists(with_suffix(&dest, ".part")).await.unwrap());
+		std::fs::remove_dir_all(dest.parent().unwrap()).unwrap();
+	}
+
+	#[tokio::test]
+	async fn downloads_in_segments() {
+		let fake = Arc::new(FakeTransport::new().serve_resumable(URL, body(), "\"v1\""));
+		let installer = installer(&body());
+		let dest = dest();
+		let download = downloader(&fake, 0).segments(4).download(&installer, &dest).await.unwrap();
+		assert_eq!(download.digests.mismatch(&installer.published.decode().unwrap()), None);
+		let requests = fake.requests();
+		assert_eq!(requests[0].method, Method::HEAD);
+		let mut ranges: Vec<_> = requests[1..].iter().map(|request| request.header_str(RANGE).unwrap()).collect();
+		ranges.sort_unstable();
+		assert_eq!(ranges, ["bytes=0-24999", "bytes=25000-49999", "bytes=50000-74999", "bytes=75000-99999"]);
+		assert!(requests[1..].iter().all(|request| request.header_str(IF_RANGE) == Some("\"v1\"")));
+		download.persist(&dest).await.unwrap();
+		assert_eq!(std::fs::read(&dest).unwrap(), body());
+		std::fs::remove_dir_all(dest.parent().unwrap()).unwrap();
+	}
+
+	#[tokio::test]
+	async fn retries_failed_segments_where_they_stopped() {
+		// The first fault is used up by the HEAD request, whose empty body is never read.
+		let fake = Arc::new(
+			FakeTransport::new()
+				.serve_resumable(URL, body(), "\"v1\"")
+				.fail(URL, Fault::Disconnect(0))
+				.fail(URL, Fault::Disconnect(10_000)),
+		);
+		let installer = installer(&body());
+		let dest = dest();
+		let download = downloader(&fake, 1).segments(2).download(&installer, &dest).await.unwrap();
+		assert_eq!(download.digests.mismatch(&installer.published.decode().unwrap()), None);
+		let requests = fake.requests();
+		assert_eq!(requests.len(), 4);
+		let retried = requests[3].header_str(RANGE).unwrap();
+		assert!(["bytes=10000-49999", "bytes=60000-99999"].contains(&retried), "{retried}");
+		download.persist(&dest).await.unwrap();
+		assert_eq!(std::fs::read(&dest).unwrap(), body());
+		std::fs::remove_dir_all(dest.parent().unwrap()).unwrap();
+	}
+
+	#[tokio::test]
+	async fn falls_back_to_a_single_stream_without_ranges() {
+		let fake = Arc::new(FakeTransport::new().serve(URL, body()));
+		let installer = installer(&body());
+		let dest = dest();
+		let download = downloader(&fake, 0).segments(4).download(&installer, &dest).await.unwrap();
+		assert_eq!(download.digests.mismatch(&installer.published.decode().unwrap()), None);
+		let requests = fake.requests();
+		assert_eq!(requests.len(), 2);
+		assert_eq!((&requests[1].method, requests[1].header_str(RANGE)), (&Method::GET, None));
+		download.discard().await.unwrap();
+		std::fs::remove_dir_all(dest.parent().unwrap()).unwrap();
+	}
+
+	#[tokio::test]
+	async fn falls_back_to_a_single_stream_when_ranges_are_ignored() {
+		let fake = Arc::new(FakeTransport::new().serve_ignoring_ranges(URL, body(), "\"v1\""));
+		let installer = installer(&body());
+		let dest = dest();
+		let events = Events::default();
+		let download =
+			downloader(&fake, 0).segments(4).progress(events.clone()).download(&installer, &dest).await.unwrap();
+		assert_eq!(events.take(), [Event::RangesIgnored.to_string()]);
+		let requests = fake.requests();
+		assert_eq!(requests[0].method, Method::HEAD);
+		let (last, ranged) = requests[1..].split_last().unwrap();
+		assert!(!ranged.is_empty() && ranged.iter().all(|request| request.header_str(RANGE).is_some()));
+		assert_eq!((&last.method, last.header_str(RANGE)), (&Method::GET, None));
+		download.persist(&dest).await.unwrap();
+		assert_eq!(std::fs::read(&dest).unwrap(), body());
+		std::fs::remove_dir_all(dest.parent().unwrap()).unwrap();
+	}
+
+	#[tokio::test]
+	async fn limits_the_rate() {
+		let fake = Arc::new(FakeTransport::new().serve(URL, body()));
+		let start = Instant::now();
+		let digests = downloader(&fake, 0).limit_rate(Some(400_000)).digests(URL).await.unwrap();
+		assert!(start.elapsed() >= Duration::from_millis(240), "took {:?}", start.elapsed());
+		assert_eq!(digests.mismatch(&installer(&body()).published.decode().unwrap()), None);
+	}
+
+	#[test]
+	fn parses_rates() {
+		assert_eq!(parse_rate("100000"), Ok(100_000));
+		assert_eq!(parse_rate("500K"), Ok(512_000));
+		assert_eq!(parse_rate("2M"), Ok(2 * 1024 * 1024));
+		assert_eq!(parse_rate("1.5m"), Ok(1_572_864));
+		assert_eq!(parse_rate("1G"), Ok(1 << 30));
+		for bad in ["", "M", "0", "-1K", "fast", "2MB", "inf"] {
+			assert!(parse_rate(bad).is_err(), "{bad}");
+		}
+	}
+}
@@ -0,0 +1,120 @@
+//! Telling failures apart, so scripts can react to each one by its exit code.
+//!
+//! Errors are still passed around as [`anyhow::Error`], with context added along the way. Failures that `nvdl` detects
+//! itself are raised as an [`Error`], and everything else is classified by what caused it.
+
+use crate::{retry::StatusError, transport::ConnectionError};
+use reqwest::StatusCode;
+use serde::Serialize;
+
+/// A failure that scripts may want to handle differently from others.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+	/// The command line asks for something that can't be done.
+	#[error("{0}")]
+	Usage(String),
+	/// An installer doesn't match the hash it should have.
+	#[error("{0}")]
+	HashMismatch(String),
+	/// A published or pinned hash isn't a valid hash.
+	#[error("{0}")]
+	InvalidHash(String),
+	/// The version or installer asked for doesn't exist.
+	#[error("{0}")]
+	NotFound(String),
+	/// The user pressed Ctrl+C.
+	#[error("{0}")]
+	Cancelled(String),
+}
+
+/// The kind of a failure, which decides the exit code. These codes are stable.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Kind {
+	/// Anything not covered below.
+	Other,
+	/// An invalid command line.
+	Usage,
+	HashMismatch,
+	InvalidHash,
+	NotFound,
+	/// The server couldn't be reached, or returned an error other than not found.
+	Network,
+	/// A file couldn't be read or written.
+	Io,
+	Cancelled,
+}
+
+impl Kind {
+	/// Classifies an error by the first cause in its chain that says what went wrong.
+	#[must_use]
+	pub fn of(err: &anyhow::Error) -> Self {
+		for cause in err.chain() {
+			if let Some(err) = cause.downcast_ref::<Error>() {
+				return match err {
+					Error::Usage(_) => Self::Usage,
+					Error::HashMismatch(_) => Self::HashMismatch,
+					Error::InvalidHash(_) => Self::InvalidHash,
+					Error::NotFound(_) => Self::NotFound,
+					Error::Cancelled(_) => Self::Cancelled,
+				};
+			}
+			if let Some(err) = cause.downcast_ref::<StatusError>() {
+				return Self::of_status(err.status);
+			}
+			if let Some(err) = cause.downcast_ref::<reqwest::Error>() {
+				return err.status().map_or(Self::Network, Self::of_status);
+			}
+			if cause.is::<ConnectionError>() {
+				return Self::Network;
+			}
+			if cause.is::<std::io::Error>() {
+				return Self::Io;
+			}
+		}
+		Self::Other
+	}
+
+	fn of_status(status: StatusCode) -> Self {
+		if matches!(status, StatusCode::NOT_FOUND | StatusCode::GONE) { Self::NotFound } else { Self::Network }
+	}
+
+	/// The process exit code for this kind of failure. Usage errors share clap's code.
+	#[must_use]
+	pub const fn exit_code(self) -> i32 {
+		match self {
+			Self::Other => 1,
+			Self::Usage => 2,
+			Self::HashMismatch => 3,
+			Self::InvalidHash => 4,
+			Self::NotFound => 5,
+			Self::Network => 6,
+			Self::Io => 7,
+			Self::Cancelled => 130,
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use anyhow::{Context, anyhow};
+
+	#[test]
+	fn classifies_by_first_known_cause() {
+		let io = || std::io::Error::new(std::io::ErrorKind::PermissionDenied, "denied");
+		let status = |status| StatusError { status, url: "https://example.com/".to_owned(), retry_after: None };
+		let cases = [
+			(anyhow!("something else"), Kind::Other),
+			(anyhow!(Error::NotFound("gone".to_owned())).context("Failed to look it up."), Kind::NotFound),
+			(Err::<(), _>(io()).context("Failed to read nvda.exe.").unwrap_err(), Kind::Io),
+			(anyhow!(status(StatusCode::NOT_FOUND)), Kind::NotFound),
+			(anyhow!(status(StatusCode::BAD_GATEWAY)).context("Failed to retrieve download URL."), Kind::Network),
+			(anyhow!(Error::Cancelled("Download cancelled.".to_owned())), Kind::Cancelled),
+		];
+		for (err, kind) in cases {
+			assert_eq!(Kind::of(&err), kind, "classifying {err:#}");
+			assert_ne!(kind.exit_code(), 0);
+		}
+	}
+}
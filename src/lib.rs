@@ -0,0 +1,51 @@
+//! Resolving, downloading and verifying installers for the NVDA screen reader.
+//!
+//! The `nvdl` command-line tool is a thin front end over this library:
+//!
+//! - A [`Resolver`] turns a channel such as `stable`, a release number or a version requirement into an
+//!   [`Installer`]: its download URL, and the hashes it should have. It can also pin one in a lockfile entry.
+//! - A [`Downloader`] fetches installers, retrying and resuming interrupted transfers, hashing them as they arrive and
+//!   reporting on them through a [`Progress`].
+//! - A [`Verifier`] checks installers on disk against their official hashes, or against a checksum manifest.
+//!
+//! All of them send their requests through a [`Transport`](transport::Transport), which is usually an
+//! [`HttpTransport`](transport::HttpTransport), but can be a [`FakeTransport`](transport::FakeTransport) in tests.
+//!
+//! ```no_run
+//! use nvdl::{Downloader, Endpoint, Resolver, Target, transport::HttpTransport};
+//! use std::sync::Arc;
+//!
+//! # async fn example() -> anyhow::Result<()> {
+//! let transport = Arc::new(HttpTransport::default());
+//! let installer = Resolver::new(transport.clone()).resolve(&Target::Endpoint(Endpoint::Stable)).await?;
+//! let download = Downloader::new(transport).download(&installer, "nvda.exe".as_ref()).await?;
+//! if let Some(expected) = installer.published.decode()
+//!     && download.digests.mismatch(&expected).is_none()
+//! {
+//!     download.persist("nvda.exe".as_ref()).await?;
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+#![warn(clippy::all, clippy::pedantic, clippy::nursery)]
+
+pub mod api;
+pub mod checksum;
+pub mod download;
+pub mod error;
+pub mod lockfile;
+pub mod releases;
+pub mod requirement;
+mod resolve;
+pub mod retry;
+pub mod sums;
+#[cfg(test)]
+mod testing;
+pub mod transport;
+mod verify;
+pub mod version;
+
+pub use download::{Downloader, Event, Progress, Transfer};
+pub use resolve::{Endpoint, Installer, Resolver, Target};
+pub use verify::{Listed, Verification, Verifier};
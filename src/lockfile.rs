@@ -0,0 +1,146 @@
+//! `nvdl.lock`: user-managed pins of NVDA installers to exact URLs and hashes.
+//!
+//! A lockfile works like the built-in XP and Win7 pins: each entry records the version someone asked for (such as
+//! `stable`), and the URL and SHA-1 hash it resolved to when the lockfile was written.
+
+use crate::{
+	checksum::{Published, decode},
+	download::write_replacing,
+	error::Error,
+	resolve::Installer,
+	version::NvdaVersion,
+};
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use std::{fs, path::Path};
+
+/// The default lockfile name.
+pub const DEFAULT_LOCKFILE: &str = "nvdl.lock";
+
+const HEADER: &str =
+	"# This file is generated by `nvdl lock`. `nvdl sync --locked` downloads exactly these installers.\n\n";
+
+/// The contents of a lockfile.
+#[derive(Serialize, Deserialize, Default, Debug, PartialEq, Eq)]
+pub struct Lockfile {
+	#[serde(default, rename = "installer")]
+	pub installers: Vec<Entry>,
+}
+
+/// A pinned installer.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Entry {
+	/// What was asked for, such as `stable` or `2023.3.4`.
+	pub target: String,
+	/// The NVDA version the target resolved to, if it could be worked out from the installer's name.
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub version: Option<NvdaVersion>,
+	pub url: String,
+	/// The SHA-1 hash of the installer, in hex.
+	pub sha1: String,
+	/// The SHA-256 hash of the installer, in hex.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub sha256: Option<String>,
+}
+
+impl Entry {
+	/// The hashes the installer is pinned to.
+	#[must_use]
+	pub fn published(&self) -> Published {
+		Published { sha1: Some(self.sha1.clone()), sha256: self.sha256.clone() }
+	}
+
+	/// The pinned installer.
+	#[must_use]
+	pub fn installer(&self) -> Installer {
+		Installer { url: self.url.clone(), published: self.published() }
+	}
+}
+
+impl Lockfile {
+	/// Reads and checks a lockfile.
+	///
+	/// # Errors
+	///
+	/// Fails if the lockfile can't be read, isn't valid TOML, or has an invalid hash.
+	pub fn load(path: &Path) -> Result<Self> {
+		let text = fs::read_to_string(path).with_context(|| format!("Failed to read {}.", path.display()))?;
+		let lockfile: Self =
+			toml::from_str(&text).with_context(|| format!("{} is not a valid lockfile.", path.display()))?;
+		for entry in &lockfile.installers {
+			if decode::<20>(&entry.sha1).is_none() {
+				bail!(Error::InvalidHash(format!(
+					"{} has an invalid SHA-1 hash for {}: {:?}.",
+					path.display(),
+					entry.target,
+					entry.sha1
+				)));
+			}
+			if let Some(sha256) = &entry.sha256
+				&& decode::<32>(sha256).is_none()
+			{
+				bail!(Error::InvalidHash(format!(
+					"{} has an invalid SHA-256 hash for {}: {sha256:?}.",
+					path.display(),
+					entry.target
+				)));
+			}
+		}
+		Ok(lockfile)
+	}
+
+	/// Writes the lockfile, replacing any existing one in one step, so a failed write leaves the old one intact.
+	///
+	/// # Errors
+	///
+	/// Fails if the lockfile can't be written.
+	pub fn save(&self, path: &Path) -> Result<()> {
+		let text = format!("{HEADER}{}", toml::to_string(self)?);
+		write_replacing(path, text.as_bytes()).with_context(|| format!("Failed to write {}.", path.display()))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::testing::temp_dir;
+
+	#[test]
+	fn replaces_lockfile_on_save() {
+		let dir = temp_dir();
+		let path = dir.join(DEFAULT_LOCKFILE);
+		fs::write(&path, "old").unwrap();
+		Lockfile::default().save(&path).unwrap();
+		assert_eq!(Lockfile::load(&path).unwrap(), Lockfile::default());
+		assert_eq!(fs::read_dir(&dir).unwrap().count(), 1, "the temporary file should be gone");
+
+		let missing = dir.join("missing").join(DEFAULT_LOCKFILE);
+		assert!(Lockfile::default().save(&missing).is_err());
+		fs::remove_dir_all(&dir).unwrap();
+	}
+
+	#[test]
+	fn round_trips_through_toml() {
+		let lockfile = Lockfile {
+			installers: vec![
+				Entry {
+					target: "stable".to_owned(),
+					version: Some("2024.4.2".parse().unwrap()),
+					url: "https://download.nvaccess.org/releases/2024.4.2/nvda_2024.4.2.exe".to_owned(),
+					sha1: "4a4b937d24de6e4cf9503c77619257c27bbba310".to_owned(),
+					sha256: Some("1d8bc5a7c9e1b6f0b49b1e0f4a7d6c2e9b3a8f5d7c6e4b2a19f8e7d6c5b4a392".to_owned()),
+				},
+				Entry {
+					target: "xp".to_owned(),
+					version: None,
+					url: "https://example.com/nvda_installer.exe".to_owned(),
+					sha1: "c1f2a3b4c5d6e7f8a9b0c1d2e3f4a5b6c7d8e9f0".to_owned(),
+					sha256: None,
+				},
+			],
+		};
+		let text = toml::to_string(&lockfile).unwrap();
+		assert!(text.contains("[[installer]]\ntarget = \"stable\"\nversion = \"2024.4.2\"\n"), "{text}");
+		assert_eq!(toml::from_str::<Lockfile>(&text).unwrap(), lockfile);
+	}
+}
@@ -2,42 +2,228 @@
 //!
 //! This tool allows users to download the latest NVDA versions or retrieve
 //! direct download links for specific versions (stable, alpha, beta, XP, Win7).
+//! It's a front end over the `nvdl` library, which does the resolving, downloading and verifying.
 
 #![warn(clippy::all, clippy::pedantic, clippy::nursery)]
 
-use anyhow::{Context, Result};
-use clap::{Args, Parser, ValueEnum};
-use dialoguer::Confirm;
-use nvda_url::{NvdaUrl, VersionType, WIN7_HASH, WIN7_URL, XP_HASH, XP_URL};
-use reqwest::Client;
-use sha1::{Digest, Sha1};
-use std::{env::current_dir, fs::File, io::Write, process::Command};
+mod output;
+mod policy;
+mod progress;
+mod report;
+
+use anyhow::{Context, Result, bail};
+use clap::{Args, Parser, Subcommand};
+use nvdl::{
+	Downloader, Endpoint, Installer, Resolver, Target, Verifier, api,
+	checksum::{self, Algo, Expected},
+	download,
+	error::{Error, Kind},
+	lockfile::{self, Lockfile},
+	releases,
+	requirement::VersionReq,
+	retry::{Retry, parse_seconds},
+	sums,
+	transport::{HttpOptions, HttpTransport, Transport},
+	version::{Channel, NvdaVersion},
+};
+use output::{Destination, Output, Vars};
+use policy::{OnHashMismatch, OnInvalidHash, Policies, confirm};
+use progress::Progress;
+use report::{Format, Report, Saved};
+use std::{
+	env::{self, current_dir},
+	ffi::OsString,
+	fmt::Display,
+	fs,
+	path::{Path, PathBuf},
+	process::{self, Command},
+	sync::Arc,
+	time::{Duration, Instant},
+};
 
 /// Defines the command-line interface for `nvdl`.
 #[derive(Parser)]
 #[command(name = "nvdl", version, about)]
 struct Cli {
-	/// The NVDA version to retrieve (default: stable).
-	#[arg(value_enum, default_value_t = Endpoint::Stable)]
-	endpoint: Endpoint,
+	#[command(subcommand)]
+	command: Option<Commands>,
+	/// The NVDA version to retrieve: stable, alpha, beta, xp, win7, a release number such as 2023.3.4, or a requirement
+	/// such as 2024.* (see --match).
+	#[arg(value_name = "VERSION", value_parser = Target::parse, default_value = "stable")]
+	target: Target,
+	/// Retrieve a specific NVDA release by number, such as 2023.3.4.
+	#[arg(long, value_name = "NUMBER", conflicts_with = "target")]
+	version_number: Option<NvdaVersion>,
+	/// Retrieve the newest release matching a requirement, such as `2024.*`, `>=2023.3,<2024.1` or `~2024.1`.
+	#[arg(long = "match", value_name = "REQUIREMENT", conflicts_with_all = ["target", "version_number"])]
+	requirement: Option<VersionReq>,
 	/// Display the installer's direct download link rather than downloading it.
 	#[arg(short, long)]
 	url: bool,
 	/// Display the installer's hash rather than downloading it.
 	#[arg(short, long)]
 	checksum: bool,
+	/// The hash to display with --checksum. SHA-512 is also added to machine-readable output when chosen.
+	#[arg(long, global = true, value_enum, default_value_t = Algo::Sha1)]
+	algo: Algo,
+	/// How to print results.
+	#[arg(long, global = true, value_enum, default_value_t = Format::Text)]
+	format: Format,
+	/// The base URL of the nvda.zip API, or a mirror of it.
+	#[arg(long, global = true, env = "NVDL_API_BASE", value_name = "URL", default_value = api::DEFAULT_API_BASE)]
+	api_base: String,
+	/// The base URL of the NV Access releases server, or a mirror of it.
+	#[arg(
+		long,
+		global = true,
+		env = "NVDL_RELEASES_BASE",
+		value_name = "URL",
+		default_value = releases::DEFAULT_RELEASES_BASE
+	)]
+	releases_base: String,
+	#[command(flatten)]
+	output: Output,
+	/// Download the installer even if a matching one already exists.
+	#[arg(short, long, global = true)]
+	force: bool,
+	/// Download no faster than this many bytes per second, such as 500K or 2M.
+	#[arg(long, global = true, value_name = "RATE", value_parser = download::parse_rate)]
+	limit_rate: Option<u64>,
+	/// Download the installer in this many parts at once, if the server allows it. Can help on high-latency links.
+	#[arg(long, global = true, value_name = "COUNT", default_value_t = 1, value_parser = clap::value_parser!(u64).range(1..=16))]
+	segments: u64,
 	#[command(flatten)]
 	run: Run,
+	#[command(flatten)]
+	progress: Progress,
+	#[command(flatten)]
+	retry: RetryArgs,
+	#[command(flatten)]
+	http: HttpArgs,
+	#[command(flatten)]
+	policies: Policies,
+}
+
+#[derive(Subcommand)]
+enum Commands {
+	/// List the NVDA versions available from NV Access, newest first.
+	List(List),
+	/// Pin NVDA versions to exact installers in a lockfile.
+	Lock(Lock),
+	/// Download the installers pinned in a lockfile.
+	Sync(Sync),
+	/// Check an installer on disk against its official hash.
+	Verify(Verify),
+}
+
+#[derive(Args)]
+struct List {
+	/// Only list versions this new or newer, such as 2022.1. Alpha snapshots are newer than every release.
+	#[arg(long, value_name = "VERSION")]
+	since: Option<NvdaVersion>,
+	/// Only list versions from this channel. Alpha snapshots are only listed when asked for.
+	#[arg(long, value_enum)]
+	channel: Option<Channel>,
+	/// List at most this many versions.
+	#[arg(long, value_name = "COUNT")]
+	limit: Option<usize>,
+	/// The URL of the NV Access alpha snapshots index, or a mirror of it.
+	#[arg(long, env = "NVDL_SNAPSHOTS_URL", value_name = "URL", default_value = releases::DEFAULT_SNAPSHOTS_URL)]
+	snapshots_url: String,
+}
+
+#[derive(Args)]
+struct Lock {
+	/// The NVDA versions to pin, such as stable, xp, 2023.3.4 or 2024.*.
+	#[arg(value_name = "VERSION", value_parser = Target::parse, default_value = "stable")]
+	targets: Vec<Target>,
+	/// The lockfile to write.
+	#[arg(long, value_name = "PATH", default_value = lockfile::DEFAULT_LOCKFILE)]
+	lockfile: PathBuf,
+}
+
+#[derive(Args)]
+struct Sync {
+	/// Download exactly the pinned installers, rather than updating the lockfile first.
+	#[arg(long)]
+	locked: bool,
+	/// The lockfile to read.
+	#[arg(long, value_name = "PATH", default_value = lockfile::DEFAULT_LOCKFILE)]
+	lockfile: PathBuf,
 }
 
 #[derive(Args)]
+struct Verify {
+	/// The installer to check.
+	#[arg(required_unless_present = "sums")]
+	file: Option<PathBuf>,
+	/// Check every file listed in a checksum file such as SHA256SUMS, like `sha256sum -c`.
+	#[arg(long, value_name = "FILE", conflicts_with_all = ["file", "channel", "version", "sha1", "sha256"])]
+	sums: Option<PathBuf>,
+	/// Check against the current installer for a channel.
+	#[arg(long, value_enum, conflicts_with_all = ["version", "sha1", "sha256"])]
+	channel: Option<Endpoint>,
+	/// Check against a specific release, such as 2024.1 (default: the version in the file's name).
+	#[arg(long, value_name = "NUMBER", conflicts_with_all = ["sha1", "sha256"])]
+	version: Option<NvdaVersion>,
+	/// Check against a SHA-1 hash.
+	#[arg(long, value_name = "HEX", value_parser = parse_hash::<20>)]
+	sha1: Option<[u8; 20]>,
+	/// Check against a SHA-256 hash.
+	#[arg(long, value_name = "HEX", value_parser = parse_hash::<32>)]
+	sha256: Option<[u8; 32]>,
+}
+
+fn parse_hash<const N: usize>(s: &str) -> Result<[u8; N], String> {
+	checksum::decode(s).ok_or_else(|| format!("expected a hash of {} hex digits", N * 2))
+}
+
+impl Cli {
+	/// The NVDA version to retrieve, from the positional argument, `--version-number` or `--match`.
+	fn target(&self) -> Target {
+		if let Some(requirement) = &self.requirement {
+			return Target::Match(requirement.clone());
+		}
+		self.version_number.clone().map_or_else(|| self.target.clone(), Target::Release)
+	}
+
+	/// A resolver for the servers chosen on the command line.
+	fn resolver(&self, transport: Arc<dyn Transport>) -> Resolver {
+		Resolver::new(transport)
+			.api_base(&self.api_base)
+			.releases_base(&self.releases_base)
+			.retry(self.retry.retry())
+			.progress(self.progress)
+	}
+
+	/// A downloader that reports progress and computes hashes as chosen on the command line.
+	fn downloader(&self, transport: Arc<dyn Transport>) -> Downloader {
+		Downloader::new(transport)
+			.retry(self.retry.retry())
+			.progress(self.progress)
+			.sha512(self.algo == Algo::Sha512)
+			.limit_rate(self.limit_rate)
+			.segments(self.segments)
+	}
+
+	/// Tells the user about something, keeping standard output clear for machine-readable formats.
+	fn note(&self, message: impl Display) {
+		if self.format == Format::Text {
+			println!("{message}");
+		} else {
+			eprintln!("{message}");
+		}
+	}
+}
+
+#[derive(Args, Clone, Copy)]
 #[group(multiple = false, conflicts_with_all=["url", "checksum"])]
 struct Run {
 	/// Run the installer after downloading.
-	#[arg(short = 'y', long)]
+	#[arg(short = 'y', long, global = true)]
 	run: bool,
 	/// Do not run the installer after downloading.
-	#[arg(short = 'n', long)]
+	#[arg(short = 'n', long, global = true)]
 	no_run: bool,
 }
 
@@ -52,102 +238,535 @@ impl Run {
 	}
 }
 
-/// Defines the available NVDA version types that can be retrieved.
-#[derive(ValueEnum, Clone, Debug)]
-enum Endpoint {
-	/// Stable release version.
-	Stable,
-	/// Snapshot alpha version.
-	Alpha,
-	/// Beta release version.
-	Beta,
-	/// The last version compatible with Windows XP.
-	Xp,
-	/// The last version compatible with Windows 7.
-	Win7,
+/// How to retry failed requests.
+#[derive(Args, Clone, Copy)]
+struct RetryArgs {
+	/// How many times to retry a failed request before giving up.
+	#[arg(long, global = true, value_name = "COUNT", default_value_t = 3)]
+	retries: u32,
+	/// How long to wait before the first retry, in seconds. Doubles with each retry.
+	#[arg(long, global = true, value_name = "SECONDS", default_value_t = 1.0, value_parser = parse_seconds)]
+	retry_delay: f64,
+	/// The longest to wait between retries, in seconds, unless the server asks for longer, up to 5 minutes.
+	#[arg(long, global = true, value_name = "SECONDS", default_value_t = 30.0, value_parser = parse_seconds)]
+	retry_max_delay: f64,
 }
 
-impl Endpoint {
-	const fn as_version_type(&self) -> Option<VersionType> {
-		match self {
-			Self::Stable => Some(VersionType::Stable),
-			Self::Alpha => Some(VersionType::Alpha),
-			Self::Beta => Some(VersionType::Beta),
-			_ => None,
-		}
+impl RetryArgs {
+	fn retry(self) -> Retry {
+		Retry::new(
+			self.retries,
+			Duration::from_secs_f64(self.retry_delay),
+			Duration::from_secs_f64(self.retry_max_delay),
+		)
 	}
+}
+
+/// How to reach servers: through which proxy, trusting which certificates, and how long to wait for them.
+#[derive(Args)]
+struct HttpArgs {
+	/// Send requests through this proxy. Its URL can include a user name and password. Hosts excluded by the
+	/// no-proxy environment variable are still reached directly.
+	#[arg(long, global = true, value_name = "URL")]
+	proxy: Option<String>,
+	/// Also trust the certificates in this PEM file, such as a private root CA. Can be given more than once.
+	#[arg(long = "ca-cert", global = true, value_name = "PATH")]
+	ca_certs: Vec<PathBuf>,
+	/// Trust the operating system's certificate store rather than the built-in root certificates.
+	#[arg(long, global = true)]
+	system_certs: bool,
+	/// Don't check servers' certificates at all. Anyone on the network path can then swap the installer.
+	#[arg(long, global = true)]
+	insecure: bool,
+	/// Give up connecting to a server after this many seconds. 0 waits forever.
+	#[arg(long, global = true, value_name = "SECONDS", default_value_t = 30.0, value_parser = parse_seconds)]
+	connect_timeout: f64,
+	/// Give up on a response if no data arrives for this many seconds. 0 waits forever.
+	#[arg(long, global = true, value_name = "SECONDS", default_value_t = 60.0, value_parser = parse_seconds)]
+	read_timeout: f64,
+}
 
-	const fn as_fixed_version(&self) -> Option<(&'static str, &'static str)> {
-		match self {
-			Self::Xp => Some((XP_URL, XP_HASH)),
-			Self::Win7 => Some((WIN7_URL, WIN7_HASH)),
-			_ => None,
+impl HttpArgs {
+	fn options(&self) -> HttpOptions {
+		let timeout = |seconds: f64| (seconds > 0.0).then(|| Duration::from_secs_f64(seconds));
+		let mut options = HttpOptions::new()
+			.system_certs(self.system_certs)
+			.insecure(self.insecure)
+			.connect_timeout(timeout(self.connect_timeout))
+			.read_timeout(timeout(self.read_timeout));
+		if let Some(proxy) = &self.proxy {
+			options = options.proxy(proxy);
 		}
+		for path in &self.ca_certs {
+			options = options.ca_cert(path);
+		}
+		options
 	}
 }
 
-/// Main entrypoint for the `nvdl` application.
+/// Main entrypoint for the `nvdl` application. Exits with the code for the [`Kind`] of error, if there is one.
 #[tokio::main]
-async fn main() -> Result<()> {
-	let cli = Cli::parse();
-	let nvda_url = NvdaUrl::default();
-	if let Some((url, hash)) = cli.endpoint.as_fixed_version() {
-		handle_metadata(url, hash, cli.url, cli.checksum, cli.run.value()).await?;
-	} else if let Some(version_type) = cli.endpoint.as_version_type() {
-		let (url, hash) = nvda_url.get_details(version_type).await.context("Failed to retrieve download URL.")?;
-		handle_metadata(&url, &hash, cli.url, cli.checksum, cli.run.value()).await?;
+async fn main() {
+	let cli = match Cli::try_parse() {
+		Ok(cli) => cli,
+		Err(err) if err.use_stderr() && asks_for_json(env::args_os()) => {
+			let message = err.to_string();
+			let message = message.lines().next().unwrap_or_default().trim_start_matches("error: ");
+			Format::Json.print_error(&Error::Usage(message.to_owned()).into(), Kind::Usage);
+			process::exit(Kind::Usage.exit_code());
+		}
+		Err(err) => err.exit(),
+	};
+	if let Err(err) = run(&cli).await {
+		let kind = Kind::of(&err);
+		if !cli.format.print_error(&err, kind) {
+			eprintln!("Error: {err:?}");
+		}
+		process::exit(kind.exit_code());
+	}
+}
+
+/// Whether the arguments ask for JSON output, so usage errors clap reports can be printed as JSON too.
+fn asks_for_json(args: impl IntoIterator<Item = OsString>) -> bool {
+	let args: Vec<_> = args.into_iter().collect();
+	args.iter().any(|arg| arg == "--format=json")
+		|| args.windows(2).any(|pair| pair[0] == "--format" && pair[1] == "json")
+}
+
+async fn run(cli: &Cli) -> Result<()> {
+	if cli.http.insecure {
+		eprintln!(
+			"WARNING: --insecure turns off certificate checks. Anyone between you and the server can swap the installer \
+			 or the hashes it's checked against."
+		);
+	}
+	let transport: Arc<dyn Transport> = Arc::new(HttpTransport::with_options(&cli.http.options())?);
+	let (resolver, downloader) = (cli.resolver(transport.clone()), cli.downloader(transport));
+	match &cli.command {
+		Some(Commands::List(list)) => return list_versions(&resolver, list, cli).await,
+		Some(Commands::Lock(lock)) => return write_lockfile(&resolver, &downloader, lock, cli).await,
+		Some(Commands::Sync(sync)) => return sync_lockfile(&resolver, &downloader, sync, cli).await,
+		Some(Commands::Verify(verify)) => return verify_file(&resolver, &downloader, verify, cli).await,
+		None => {}
+	}
+	let installer = resolve(&resolver, &cli.target()).await?;
+	handle_metadata(&downloader, &installer, cli).await
+}
+
+/// Prints the NVDA versions available from NV Access.
+async fn list_versions(resolver: &Resolver, list: &List, cli: &Cli) -> Result<()> {
+	let mut versions = if list.channel == Some(Channel::Alpha) {
+		resolver.snapshots(&list.snapshots_url).await?
+	} else {
+		resolver.releases().await?
+	};
+	versions.retain(|release| list.channel.is_none_or(|channel| release.channel == channel));
+	if let Some(since) = &list.since {
+		versions.retain(|release| release.version >= *since);
+	}
+	versions.truncate(list.limit.unwrap_or(usize::MAX));
+	match cli.format {
+		Format::Text if versions.is_empty() => println!("No matching versions found."),
+		Format::Text => {
+			for release in &versions {
+				println!("{} ({})", release.version, release.channel.name());
+			}
+		}
+		Format::Json => println!("{}", serde_json::to_string(&versions)?),
+		Format::Tsv => {
+			println!("version\tchannel\turl");
+			for release in &versions {
+				println!("{}\t{}\t{}", release.version, release.channel.name(), release.url);
+			}
+		}
+	}
+	Ok(())
+}
+
+/// Hashes an installer on disk and compares it with the hash it should have, failing with [`Error::HashMismatch`] if
+/// they differ. Only an intact installer is reported.
+async fn verify_file(resolver: &Resolver, downloader: &Downloader, verify: &Verify, cli: &Cli) -> Result<()> {
+	let verifier = Verifier::new(downloader);
+	if let Some(manifest) = &verify.sums {
+		return verify_sums(&verifier, manifest, cli).await;
+	}
+	let file = verify.file.as_deref().context("No file to verify.")?;
+	let expected = if verify.sha1.is_some() || verify.sha256.is_some() {
+		Expected { sha1: verify.sha1, sha256: verify.sha256 }
+	} else {
+		let target = match (&verify.channel, &verify.version) {
+			(Some(endpoint), _) => Target::Endpoint(endpoint.clone()),
+			(None, Some(version)) => Target::Release(version.clone()),
+			(None, None) => {
+				let name = file.file_name().and_then(|name| name.to_str()).unwrap_or_default();
+				Target::Release(NvdaVersion::from_installer_name(name).ok_or_else(|| {
+					Error::Usage(format!(
+						"Can't tell which NVDA version {} is from its name. Use --channel, --version, --sha1 or --sha256.",
+						file.display()
+					))
+				})?)
+			}
+		};
+		let installer = resolve(resolver, &target).await?;
+		verifier.expected(&installer).await?
+	};
+	let verification = verifier.verify(file, &expected).await?;
+	let path = file.display();
+	// A mismatch is reported as the error alone, so machine-readable output stays a single document.
+	if let Some(mismatch) = verification.mismatch {
+		return Err(Error::HashMismatch(format!("{path} does not match. It has {mismatch}.")).into());
+	}
+	let actual = verification.digests;
+	let hex = |hash: Option<&[u8]>| hash.map(base16ct::lower::encode_string);
+	let (sha1, sha256) = (base16ct::lower::encode_string(&actual.sha1), base16ct::lower::encode_string(&actual.sha256));
+	let expected_sha1 = hex(expected.sha1.as_ref().map(|hash| &hash[..]));
+	let expected_sha256 = hex(expected.sha256.as_ref().map(|hash| &hash[..]));
+	match cli.format {
+		Format::Text => println!("{path} is intact (SHA-256 {sha256})."),
+		Format::Json => println!(
+			"{}",
+			serde_json::json!({
+				"path": path.to_string(),
+				"sha1": sha1,
+				"sha256": sha256,
+				"expected_sha1": expected_sha1,
+				"expected_sha256": expected_sha256,
+				"matches": true,
+			})
+		),
+		Format::Tsv => {
+			println!("path\tsha1\tsha256\texpected_sha1\texpected_sha256\tmatches");
+			println!(
+				"{path}\t{sha1}\t{sha256}\t{}\t{}\ttrue",
+				expected_sha1.unwrap_or_default(),
+				expected_sha256.unwrap_or_default()
+			);
+		}
 	}
 	Ok(())
 }
 
+/// Checks every file listed in a checksum file, like `sha256sum -c`, failing with [`Error::HashMismatch`] if any of
+/// them is missing or doesn't match.
+async fn verify_sums(verifier: &Verifier<'_>, manifest: &Path, cli: &Cli) -> Result<()> {
+	let listed = verifier.check_sums(manifest).await?;
+	let failed: Vec<_> = listed.iter().filter(|file| !file.matches()).map(|file| file.filename.as_str()).collect();
+	// Failures are reported as the error alone in machine-readable formats, so the output stays a single document.
+	if !failed.is_empty() && cli.format != Format::Text {
+		bail!(sums_mismatch(&failed, listed.len()));
+	}
+	match cli.format {
+		Format::Text => {
+			for file in &listed {
+				match &file.actual {
+					_ if file.matches() => println!("{}: OK", file.filename),
+					Some(_) => println!("{}: FAILED", file.filename),
+					None => println!("{}: FAILED open or read", file.filename),
+				}
+			}
+		}
+		Format::Json => {
+			let results: Vec<_> = listed
+				.iter()
+				.map(|file| {
+					serde_json::json!({
+						"path": file.filename,
+						"algo": file.algo.name(),
+						"expected": file.expected,
+						"actual": file.actual,
+						"matches": file.matches(),
+					})
+				})
+				.collect();
+			println!("{}", serde_json::to_string(&results)?);
+		}
+		Format::Tsv => {
+			println!("path\talgo\texpected\tactual\tmatches");
+			for file in &listed {
+				println!(
+					"{}\t{}\t{}\t{}\t{}",
+					file.filename,
+					file.algo.name(),
+					file.expected,
+					file.actual.as_deref().unwrap_or_default(),
+					file.matches()
+				);
+			}
+		}
+	}
+	if !failed.is_empty() {
+		bail!(sums_mismatch(&failed, listed.len()));
+	}
+	Ok(())
+}
+
+/// The error for files in a checksum file that are missing or don't match.
+fn sums_mismatch(failed: &[&str], listed: usize) -> Error {
+	Error::HashMismatch(format!("{} of {listed} listed files did not match: {}.", failed.len(), failed.join(", ")))
+}
+
+/// Resolves NVDA versions to exact installers, and pins them in a lockfile.
+async fn write_lockfile(resolver: &Resolver, downloader: &Downloader, lock: &Lock, cli: &Cli) -> Result<()> {
+	let mut lockfile = Lockfile::default();
+	for target in &lock.targets {
+		let entry = resolver.lock(downloader, target, None).await?;
+		cli.note(format_args!(
+			"Locked {target} to {} (SHA-256 {}).",
+			entry.url,
+			entry.sha256.as_deref().unwrap_or_default()
+		));
+		lockfile.installers.push(entry);
+	}
+	lockfile.save(&lock.lockfile)?;
+	cli.note(format_args!("Wrote {}.", lock.lockfile.display()));
+	let reports: Vec<_> = lockfile.installers.iter().map(Report::locked).collect();
+	cli.format.print_all(&reports);
+	Ok(())
+}
+
+/// Downloads the installers pinned in a lockfile, first updating it to the latest installers unless `--locked` is given.
+async fn sync_lockfile(resolver: &Resolver, downloader: &Downloader, sync: &Sync, cli: &Cli) -> Result<()> {
+	let mut lockfile = Lockfile::load(&sync.lockfile)?;
+	if !sync.locked {
+		let mut changed = false;
+		for entry in &mut lockfile.installers {
+			let target = Target::parse(&entry.target).map_err(anyhow::Error::msg)?;
+			let updated = resolver.lock(downloader, &target, Some(entry)).await?;
+			if updated != *entry {
+				cli.note(format_args!("Updated {target} from {} to {}.", entry.url, updated.url));
+				*entry = updated;
+				changed = true;
+			}
+		}
+		if changed {
+			lockfile.save(&sync.lockfile)?;
+		}
+	}
+	check_destinations(&lockfile, cli)?;
+	let mut reports = Vec::with_capacity(lockfile.installers.len());
+	for entry in &lockfile.installers {
+		let mut report = Report::locked(entry);
+		let target = Target::parse(&entry.target).map_err(anyhow::Error::msg)?;
+		let installer = entry.installer();
+		if let Some(saved) = download_and_prompt(downloader, target.name(), &installer, HashCheck::Require, cli).await?
+		{
+			report.saved(&saved);
+		}
+		reports.push(report);
+	}
+	cli.format.print_all(&reports);
+	Ok(())
+}
+
+/// Makes sure every installer in a lockfile is saved somewhere different, so no download overwrites another.
+fn check_destinations(lockfile: &Lockfile, cli: &Cli) -> Result<()> {
+	if lockfile.installers.len() < 2 {
+		return Ok(());
+	}
+	let mut paths = Vec::with_capacity(lockfile.installers.len());
+	for entry in &lockfile.installers {
+		let channel = Target::parse(&entry.target).map_err(anyhow::Error::msg)?.name();
+		let path = match cli.output.destination(&Vars { channel, url: &entry.url })? {
+			Destination::Stdout => {
+				bail!(Error::Usage("More than one installer can't be written to standard output.".to_owned()));
+			}
+			Destination::File(path) => path,
+		};
+		if paths.contains(&path) {
+			bail!(Error::Usage(format!(
+				"More than one installer would be saved as {}. Use {{channel}}, {{version}} or {{filename}} in --output.",
+				path.display()
+			)));
+		}
+		paths.push(path);
+	}
+	Ok(())
+}
+
+/// Works out the installer for a version, saying which release a requirement picked.
+async fn resolve(resolver: &Resolver, target: &Target) -> Result<Installer> {
+	let installer = resolver.resolve(target).await?;
+	if let Target::Match(requirement) = target
+		&& let Some(version) = installer.version()
+	{
+		eprintln!("{requirement} matches NVDA {version}.");
+	}
+	Ok(installer)
+}
+
 /// Handles either downloading NVDA or printing the download URL and/or hash.
-async fn handle_metadata(url: &str, hash: &str, print_url: bool, print_hash: bool, run: Option<bool>) -> Result<()> {
-	if print_url && print_hash {
+async fn handle_metadata(downloader: &Downloader, installer: &Installer, cli: &Cli) -> Result<()> {
+	let (url, published, channel) = (installer.url.as_str(), &installer.published, cli.target().name());
+	let mut report = Report::new(channel, installer.version(), url, published);
+	if cli.checksum && published.get(cli.algo).is_none() {
+		report.hashed(&downloader.digests(url).await?);
+	}
+	let hash = report.hash(cli.algo).unwrap_or_default().to_owned();
+	if cli.format != Format::Text {
+		if !cli.url
+			&& !cli.checksum
+			&& let Some(saved) = download_and_prompt(downloader, channel, installer, HashCheck::Policy, cli).await?
+		{
+			report.saved(&saved);
+		}
+		cli.format.print(&report);
+	} else if cli.url && cli.checksum {
 		println!("{url} ({hash})");
-	} else if print_url {
+	} else if cli.url {
 		println!("{url}");
-	} else if print_hash {
+	} else if cli.checksum {
 		println!("{hash}");
 	} else {
-		download_and_prompt(url, hash, run).await?;
+		download_and_prompt(downloader, channel, installer, HashCheck::Policy, cli).await?;
 	}
 	Ok(())
 }
 
+/// How strictly to check an installer against the hash it's expected to have.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum HashCheck {
+	/// Follow `--on-hash-mismatch` and `--on-invalid-hash`.
+	Policy,
+	/// Always fail, as the hash is pinned.
+	Require,
+}
+
 /// Downloads the NVDA installer from a particular URL, and asks the user if they'd like to run it if they're on Windows.
-async fn download_and_prompt(url: &str, hash: &str, run: Option<bool>) -> Result<()> {
-	let mut expected_hash = [0u8; 20];
-	let compare_hashes = match base16ct::mixed::decode(hash, &mut expected_hash) {
-		Err(_) => {
-			if !confirm("The server returned an invalid hash. Download anyway?", false) {
-				return Ok(());
-			}
-			false
+///
+/// Returns where the installer was saved, unless it was written to standard output. If there's no hash to check it
+/// against, the installer is saved unchecked. If the hash is bad, `--on-hash-mismatch` and `--on-invalid-hash` decide
+/// whether to carry on or fail.
+async fn download_and_prompt(
+	downloader: &Downloader,
+	channel: &str,
+	installer: &Installer,
+	check: HashCheck,
+	cli: &Cli,
+) -> Result<Option<Saved>> {
+	let url = installer.url.as_str();
+	let decoded = installer.published.decode().filter(|expected| !expected.is_empty());
+	let expected = if let Some(expected) = decoded {
+		expected
+	} else if check == HashCheck::Require {
+		bail!(Error::InvalidHash(format!("The pinned hash for {url} is missing or not valid.")));
+	} else {
+		let problem = if installer.published.is_empty() {
+			"No hash is published for this version"
+		} else {
+			"The server returned an invalid hash"
+		};
+		let skip = match cli.policies.on_invalid_hash() {
+			OnInvalidHash::Fail => false,
+			OnInvalidHash::SkipVerify => true,
+			OnInvalidHash::Prompt => confirm(&format!("{problem}. Download anyway?"), false),
+		};
+		if !skip {
+			bail!(Error::InvalidHash(format!(
+				"{problem} for {url}, so the installer wasn't downloaded. Use --on-invalid-hash skip-verify to download \
+				 it without verifying it."
+			)));
+		}
+		eprintln!("{problem}, so the download can't be verified.");
+		Expected::default()
+	};
+	let path = match cli.output.destination(&Vars { channel, url })? {
+		Destination::Stdout if cli.format != Format::Text => {
+			bail!(Error::Usage(
+				"The installer can't be written to standard output when --format is json or tsv.".to_owned()
+			));
+		}
+		Destination::Stdout => {
+			let keep = check == HashCheck::Policy && cli.policies.on_hash_mismatch() == OnHashMismatch::Keep;
+			download_to_stdout(downloader, url, &expected, keep).await?;
+			return Ok(None);
 		}
-		Ok(_) => true,
+		Destination::File(path) => path,
 	};
-	println!("Downloading...");
-	let response = Client::new().get(url).send().await?.error_for_status()?;
-	let content = response.bytes().await?;
-	let actual_hash = Sha1::digest(&content);
-	if compare_hashes && actual_hash.as_slice() != expected_hash && !confirm("Hashes do not match. Save anyway?", false)
+	if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+		fs::create_dir_all(dir).with_context(|| format!("Failed to create {}.", dir.display()))?;
+	}
+	let start = Instant::now();
+	if !expected.is_empty()
+		&& !cli.force
+		&& path.is_file()
+		&& let digests = downloader.hash_file(&path).await?
+		&& digests.mismatch(&expected).is_none()
 	{
-		return Ok(());
+		cli.note(format_args!("{} is already up to date.", path.display()));
+		let saved = Saved { size: fs::metadata(&path)?.len(), digests, path, elapsed: start.elapsed() };
+		write_checksums(&saved, cli)?;
+		offer_to_run(&saved.path, cli, "Run the existing installer now?")?;
+		return Ok(Some(saved));
+	}
+	eprintln!("Downloading...");
+	let download = tokio::select! {
+		download = downloader.download(installer, &path) => Some(download),
+		_ = tokio::signal::ctrl_c() => None,
+	};
+	let Some(download) = download else {
+		download::clean_up(&path).await;
+		bail!(Error::Cancelled("Download cancelled.".to_owned()));
+	};
+	let download = download?;
+	if let Some(mismatch) = download.digests.mismatch(&expected) {
+		if check == HashCheck::Require {
+			download.discard().await?;
+			bail!(Error::HashMismatch(format!(
+				"The installer at {url} doesn't match its pinned hash. It has {mismatch}."
+			)));
+		}
+		let keep = match cli.policies.on_hash_mismatch() {
+			OnHashMismatch::Fail => false,
+			OnHashMismatch::Keep => true,
+			OnHashMismatch::Prompt => confirm("Hashes do not match. Save anyway?", false),
+		};
+		if !keep {
+			download.discard().await?;
+			bail!(Error::HashMismatch(format!(
+				"The installer at {url} doesn't match its published hash, so it was deleted. It has {mismatch}."
+			)));
+		}
+		eprintln!("Warning: the installer doesn't match its published hash. It has {mismatch}.");
+	}
+	let digests = download.digests;
+	download.persist(&path).await?;
+	let saved = Saved { size: fs::metadata(&path)?.len(), digests, path, elapsed: start.elapsed() };
+	cli.note(format_args!("Saved the installer to {}.", saved.path.display()));
+	write_checksums(&saved, cli)?;
+	offer_to_run(&saved.path, cli, "Installer downloaded. Run now?")?;
+	Ok(Some(saved))
+}
+
+/// Records a saved installer's hashes in the checksum files next to it, if asked to.
+fn write_checksums(saved: &Saved, cli: &Cli) -> Result<()> {
+	if cli.output.write_checksums() {
+		sums::record(&saved.path, &saved.digests)?;
 	}
-	let filename = url.rsplit('/').next().filter(|s| !s.is_empty()).unwrap_or("nvda_installer.exe");
-	let mut file = File::create(filename)?;
-	file.write_all(&content)?;
-	file.sync_data()?;
-	drop(file);
-	println!("Downloaded {filename} to the current directory.");
-	if cfg!(target_os = "windows") && run.unwrap_or_else(|| confirm("Installer downloaded. Run now?", true)) {
-		println!("Running installer...");
-		Command::new(current_dir()?.join(filename)).spawn()?.wait()?;
+	Ok(())
+}
+
+/// Runs the installer if asked to, or asks the user whether to if they're on Windows.
+fn offer_to_run(path: &Path, cli: &Cli, prompt: &str) -> Result<()> {
+	if cfg!(target_os = "windows") && cli.run.value().unwrap_or_else(|| confirm(prompt, true)) {
+		cli.note("Running installer...");
+		Command::new(current_dir()?.join(path)).spawn()?.wait()?;
 	}
 	Ok(())
 }
 
-/// Prompts the user with a yes/no prompt in the terminal. Returns false on error.
-fn confirm(prompt: &str, default_val: bool) -> bool {
-	Confirm::new().with_prompt(prompt).report(false).default(default_val).interact().unwrap_or(false)
+/// Writes the NVDA installer to standard output. The hash can only be checked once it has all been written, so a
+/// mismatch fails unless it's to be kept anyway.
+async fn download_to_stdout(downloader: &Downloader, url: &str, expected: &Expected, keep: bool) -> Result<()> {
+	eprintln!("Downloading...");
+	let digests = downloader.stream(url, tokio::io::stdout()).await?;
+	if let Some(mismatch) = digests.mismatch(expected) {
+		if !keep {
+			bail!(Error::HashMismatch(format!(
+				"Hashes do not match. The installer written to standard output is not the one the server advertised. \
+				 It has {mismatch}."
+			)));
+		}
+		eprintln!("Warning: the installer doesn't match its published hash. It has {mismatch}.");
+	}
+	Ok(())
 }
@@ -0,0 +1,157 @@
+//! Working out where a downloaded installer should be written.
+
+use anyhow::{Result, bail};
+use clap::Args;
+use nvdl::{error::Error, version::NvdaVersion};
+use std::path::PathBuf;
+
+/// Output location options.
+#[derive(Args)]
+pub struct Output {
+	/// The directory to save the installer in (default: the current directory).
+	#[arg(long = "output-dir", global = true, value_name = "DIR")]
+	dir: Option<PathBuf>,
+	/// The file to save the installer as, or `-` to write it to standard output. May contain `{channel}`, `{version}`
+	/// and `{filename}` placeholders, e.g. `{channel}-{version}.exe`.
+	#[arg(short = 'o', long = "output", global = true, value_name = "PATH")]
+	template: Option<String>,
+	/// Record the installer's hashes in SHA1SUMS and SHA256SUMS files next to it, replacing any old entries for it.
+	#[arg(long, global = true)]
+	write_checksums: bool,
+}
+
+/// Where a downloaded installer should be written.
+pub enum Destination {
+	/// Standard output.
+	Stdout,
+	/// A file on disk.
+	File(PathBuf),
+}
+
+/// The values available to `--output` templates.
+pub struct Vars<'a> {
+	/// The endpoint the installer came from, e.g. `stable`.
+	pub channel: &'a str,
+	/// The installer's download URL.
+	pub url: &'a str,
+}
+
+impl Vars<'_> {
+	/// The last path segment of the download URL.
+	pub fn filename(&self) -> &str {
+		self.url.rsplit('/').next().filter(|s| !s.is_empty()).unwrap_or("nvda_installer.exe")
+	}
+
+	/// The NVDA version, taken from an installer filename such as `nvda_2024.4.2.exe`.
+	pub fn version(&self) -> Option<NvdaVersion> {
+		NvdaVersion::from_installer_name(self.filename())
+	}
+
+	fn get(&self, name: &str) -> Option<String> {
+		match name {
+			"channel" => Some(self.channel.to_owned()),
+			// Fall back to the bare filename for installers that aren't named after their version.
+			"version" => Some(self.version().map_or_else(
+				|| {
+					let filename = self.filename();
+					filename.strip_suffix(".exe").unwrap_or(filename).to_owned()
+				},
+				|version| version.to_string(),
+			)),
+			"filename" => Some(self.filename().to_owned()),
+			_ => None,
+		}
+	}
+}
+
+impl Output {
+	/// Whether to record saved installers in checksum files.
+	pub const fn write_checksums(&self) -> bool {
+		self.write_checksums
+	}
+
+	/// Resolves the output options to a destination for an installer.
+	pub fn destination(&self, vars: &Vars) -> Result<Destination> {
+		if self.template.as_deref() == Some("-") {
+			if self.dir.is_some() {
+				bail!(Error::Usage(
+					"--output-dir can't be used when writing the installer to standard output.".to_owned()
+				));
+			}
+			return Ok(Destination::Stdout);
+		}
+		let name = match &self.template {
+			Some(template) => expand(template, vars)?,
+			None => vars.filename().to_owned(),
+		};
+		Ok(Destination::File(self.dir.as_ref().map_or_else(|| PathBuf::from(&name), |dir| dir.join(&name))))
+	}
+}
+
+/// Replaces `{name}` placeholders in a template with their values.
+fn expand(template: &str, vars: &Vars) -> Result<String> {
+	let mut expanded = String::with_capacity(template.len());
+	let mut rest = template;
+	while let Some(start) = rest.find('{') {
+		expanded.push_str(&rest[..start]);
+		let end = rest[start..]
+			.find('}')
+			.ok_or_else(|| Error::Usage(format!("Unclosed placeholder in output template {template}.")))?;
+		let name = &rest[start + 1..start + end];
+		match vars.get(name) {
+			Some(value) => expanded.push_str(&value),
+			None => bail!(Error::Usage(format!(
+				"Unknown placeholder {{{name}}} in output template. Use {{channel}}, {{version}} or {{filename}}."
+			))),
+		}
+		rest = &rest[start + end + 1..];
+	}
+	expanded.push_str(rest);
+	Ok(expanded)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const VARS: Vars = Vars { channel: "stable", url: "https://example.com/releases/2024.4.2/nvda_2024.4.2.exe" };
+
+	fn output(dir: Option<&str>, template: Option<&str>) -> Output {
+		Output { dir: dir.map(PathBuf::from), template: template.map(str::to_owned), write_checksums: false }
+	}
+
+	fn file(output: &Output, vars: &Vars) -> PathBuf {
+		match output.destination(vars).unwrap() {
+			Destination::File(path) => path,
+			Destination::Stdout => panic!("expected a file"),
+		}
+	}
+
+	#[test]
+	fn expands_placeholders() {
+		assert_eq!(expand("{channel}-{version}.exe", &VARS).unwrap(), "stable-2024.4.2.exe");
+		assert_eq!(expand("installers/{filename}", &VARS).unwrap(), "installers/nvda_2024.4.2.exe");
+		assert_eq!(expand("nvda.exe", &VARS).unwrap(), "nvda.exe");
+		let snapshot = Vars { channel: "alpha", url: "https://example.com/nvda_snapshot_alpha-1234.exe" };
+		assert_eq!(expand("{version}", &snapshot).unwrap(), "nvda_snapshot_alpha-1234");
+	}
+
+	#[test]
+	fn rejects_bad_placeholders() {
+		let unclosed = expand("{channel", &VARS).unwrap_err();
+		assert!(unclosed.to_string().contains("Unclosed placeholder"), "{unclosed}");
+		let unknown = expand("{arch}.exe", &VARS).unwrap_err();
+		assert!(unknown.to_string().contains("Unknown placeholder {arch}"), "{unknown}");
+		assert!(unknown.is::<Error>());
+	}
+
+	#[test]
+	fn chooses_destination() {
+		assert_eq!(file(&output(None, None), &VARS), PathBuf::from("nvda_2024.4.2.exe"));
+		assert_eq!(file(&output(Some("dl"), None), &VARS), PathBuf::from("dl/nvda_2024.4.2.exe"));
+		assert_eq!(file(&output(Some("dl"), Some("{channel}.exe")), &VARS), PathBuf::from("dl/stable.exe"));
+		assert!(matches!(output(None, Some("-")).destination(&VARS), Ok(Destination::Stdout)));
+		let err = output(Some("dl"), Some("-")).destination(&VARS).err().unwrap();
+		assert!(err.to_string().contains("--output-dir"), "{err}");
+	}
+}
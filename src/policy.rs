@@ -0,0 +1,97 @@
+//! Deciding what to do about a bad hash, with or without someone at the keyboard to ask.
+
+use clap::{Args, ValueEnum};
+use dialoguer::Confirm;
+use std::io::{IsTerminal, stderr, stdin};
+
+/// What to do when something's wrong with an installer's hash. Without these, `nvdl` asks if it's running in a
+/// terminal, and fails otherwise.
+#[derive(Args, Clone, Copy)]
+pub struct Policies {
+	/// What to do when a download doesn't match its published hash (default: prompt in a terminal, otherwise fail).
+	#[arg(long, global = true, value_enum, value_name = "POLICY")]
+	on_hash_mismatch: Option<OnHashMismatch>,
+	/// What to do when no hash is published, or it isn't valid (default: prompt in a terminal, otherwise fail).
+	#[arg(long, global = true, value_enum, value_name = "POLICY")]
+	on_invalid_hash: Option<OnInvalidHash>,
+}
+
+/// What to do when a download doesn't match its published hash.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OnHashMismatch {
+	/// Delete the download and fail.
+	Fail,
+	/// Keep the download anyway, with a warning.
+	Keep,
+	/// Ask whether to keep it.
+	Prompt,
+}
+
+/// What to do when no hash is published, or it isn't valid.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OnInvalidHash {
+	/// Fail without downloading.
+	Fail,
+	/// Download without verifying, with a warning.
+	SkipVerify,
+	/// Ask whether to download anyway.
+	Prompt,
+}
+
+impl Policies {
+	pub fn on_hash_mismatch(self) -> OnHashMismatch {
+		self.on_hash_mismatch_when(interactive())
+	}
+
+	pub fn on_invalid_hash(self) -> OnInvalidHash {
+		self.on_invalid_hash_when(interactive())
+	}
+
+	/// The policy for hash mismatches, given whether there's someone to ask.
+	fn on_hash_mismatch_when(self, interactive: bool) -> OnHashMismatch {
+		self.on_hash_mismatch.unwrap_or(if interactive { OnHashMismatch::Prompt } else { OnHashMismatch::Fail })
+	}
+
+	/// The policy for missing or invalid hashes, given whether there's someone to ask.
+	fn on_invalid_hash_when(self, interactive: bool) -> OnInvalidHash {
+		self.on_invalid_hash.unwrap_or(if interactive { OnInvalidHash::Prompt } else { OnInvalidHash::Fail })
+	}
+}
+
+/// Whether there's someone at a terminal to answer prompts.
+pub fn interactive() -> bool {
+	stdin().is_terminal() && stderr().is_terminal()
+}
+
+/// Prompts the user with a yes/no prompt in the terminal. Returns false on error, such as when there's no terminal.
+pub fn confirm(prompt: &str, default_val: bool) -> bool {
+	Confirm::new().with_prompt(prompt).report(false).default(default_val).interact().unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn prompts_only_in_a_terminal_by_default() {
+		let policies = Policies { on_hash_mismatch: None, on_invalid_hash: None };
+		assert_eq!(policies.on_hash_mismatch_when(true), OnHashMismatch::Prompt);
+		assert_eq!(policies.on_hash_mismatch_when(false), OnHashMismatch::Fail);
+		assert_eq!(policies.on_invalid_hash_when(true), OnInvalidHash::Prompt);
+		assert_eq!(policies.on_invalid_hash_when(false), OnInvalidHash::Fail);
+	}
+
+	#[test]
+	fn follows_explicit_policies() {
+		let policies =
+			Policies { on_hash_mismatch: Some(OnHashMismatch::Keep), on_invalid_hash: Some(OnInvalidHash::SkipVerify) };
+		for interactive in [true, false] {
+			assert_eq!(policies.on_hash_mismatch_when(interactive), OnHashMismatch::Keep);
+			assert_eq!(policies.on_invalid_hash_when(interactive), OnInvalidHash::SkipVerify);
+		}
+		let policies =
+			Policies { on_hash_mismatch: Some(OnHashMismatch::Prompt), on_invalid_hash: Some(OnInvalidHash::Fail) };
+		assert_eq!(policies.on_hash_mismatch_when(false), OnHashMismatch::Prompt);
+		assert_eq!(policies.on_invalid_hash_when(true), OnInvalidHash::Fail);
+	}
+}
@@ -0,0 +1,185 @@
+//! Download progress reporting that stays out of a screen reader's way.
+//!
+//! The default is a plain line of text every so often, which NVDA reads once and moves on from. A compact mode
+//! that redraws a single line is available for sighted terminals, and progress can be turned off entirely.
+
+use clap::{Args, ValueEnum};
+use nvdl::{Event, Transfer};
+use std::{
+	io::{Write, stderr},
+	time::{Duration, Instant},
+};
+
+/// How download progress is reported.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProgressMode {
+	/// Print a plain line of text every few percent or seconds.
+	Lines,
+	/// Redraw a single status line in place.
+	Compact,
+	/// Don't report progress.
+	None,
+}
+
+/// Progress reporting options.
+#[derive(Args, Clone, Copy)]
+pub struct Progress {
+	/// How to report download progress.
+	#[arg(long = "progress", global = true, value_enum, default_value_t = ProgressMode::Lines)]
+	mode: ProgressMode,
+	/// In lines mode, report progress every this many percent.
+	#[arg(long = "progress-step", global = true, value_name = "PERCENT", default_value_t = 10, value_parser = clap::value_parser!(u64).range(1..=100))]
+	step: u64,
+	/// In lines mode, report progress at least this often, in seconds.
+	#[arg(long = "progress-interval", global = true, value_name = "SECONDS", default_value_t = 5)]
+	interval: u64,
+}
+
+impl nvdl::Progress for Progress {
+	fn start(&self, total: Option<u64>, done: u64) -> Box<dyn Transfer> {
+		let mut reporter = Reporter { options: *self, total, done, last_bytes: done, last_time: Instant::now() };
+		if self.mode == ProgressMode::Compact {
+			reporter.redraw();
+		}
+		Box::new(reporter)
+	}
+
+	/// Prints events whatever the progress mode, since they explain delays and failures.
+	fn event(&self, event: &Event<'_>) {
+		eprintln!("{event}");
+	}
+}
+
+/// Tracks and reports the progress of a single transfer.
+pub struct Reporter {
+	options: Progress,
+	total: Option<u64>,
+	done: u64,
+	last_bytes: u64,
+	last_time: Instant,
+}
+
+impl Transfer for Reporter {
+	fn advance(&mut self, n: u64) {
+		self.done += n;
+		match self.options.mode {
+			ProgressMode::Lines if self.line_due() => {
+				eprintln!("Downloaded {}", self.describe());
+				self.mark();
+			}
+			ProgressMode::Compact if self.last_time.elapsed() >= Duration::from_millis(200) => self.redraw(),
+			ProgressMode::Lines | ProgressMode::Compact | ProgressMode::None => {}
+		}
+	}
+
+	fn finish(mut self: Box<Self>) {
+		match self.options.mode {
+			ProgressMode::Lines if self.last_bytes != self.done => eprintln!("Downloaded {}", self.describe()),
+			ProgressMode::Compact => {
+				self.redraw();
+				eprintln!();
+			}
+			_ => {}
+		}
+	}
+}
+
+impl Reporter {
+	fn redraw(&mut self) {
+		eprint!("\r{}\x1b[K", self.describe());
+		let _ = stderr().flush();
+		self.mark();
+	}
+
+	/// Whether it's time for another line: another step has been crossed, or the interval has passed since the last one.
+	fn line_due(&self) -> bool {
+		let step_reached = self
+			.percent()
+			.is_some_and(|percent| percent / self.options.step > self.percent_of(self.last_bytes) / self.options.step);
+		step_reached || self.last_time.elapsed() >= Duration::from_secs(self.options.interval)
+	}
+
+	fn mark(&mut self) {
+		self.last_bytes = self.done;
+		self.last_time = Instant::now();
+	}
+
+	fn percent(&self) -> Option<u64> {
+		self.total.filter(|&total| total > 0).map(|_| self.percent_of(self.done))
+	}
+
+	fn percent_of(&self, bytes: u64) -> u64 {
+		self.total.filter(|&total| total > 0).map_or(0, |total| (bytes.min(total) * 100) / total)
+	}
+
+	fn describe(&self) -> String {
+		match (self.percent(), self.total) {
+			(Some(percent), Some(total)) => {
+				format!("{percent}% ({} of {})", format_bytes(self.done), format_bytes(total))
+			}
+			_ => format_bytes(self.done),
+		}
+	}
+}
+
+/// Formats a byte count for humans, e.g. `40.1 MB`.
+fn format_bytes(bytes: u64) -> String {
+	const UNITS: [(u64, &str); 3] = [(1_000_000_000, "GB"), (1_000_000, "MB"), (1_000, "KB")];
+	UNITS.iter().find(|&&(size, _)| bytes >= size).map_or_else(
+		|| format!("{bytes} bytes"),
+		|&(size, unit)| format!("{}.{} {unit}", bytes / size, (bytes % size) * 10 / size),
+	)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn reporter(total: Option<u64>, step: u64, interval: u64) -> Reporter {
+		let options = Progress { mode: ProgressMode::Lines, step, interval };
+		Reporter { options, total, done: 0, last_bytes: 0, last_time: Instant::now() }
+	}
+
+	#[test]
+	fn reports_each_step() {
+		let mut reporter = reporter(Some(1000), 10, 60);
+		reporter.done = 99;
+		assert!(!reporter.line_due());
+		reporter.done = 100;
+		assert!(reporter.line_due());
+		reporter.mark();
+		reporter.done = 150;
+		assert!(!reporter.line_due(), "half a step since the last line");
+		reporter.done = 450;
+		assert!(reporter.line_due(), "several steps at once still make one line");
+	}
+
+	#[test]
+	fn reports_on_interval_without_a_total() {
+		let mut reporter = reporter(None, 10, 60);
+		reporter.done = 1_000_000;
+		assert!(!reporter.line_due());
+		reporter.last_time -= Duration::from_secs(60);
+		assert!(reporter.line_due());
+	}
+
+	#[test]
+	fn describes_progress() {
+		let mut reporter = reporter(Some(2_500_000), 10, 60);
+		reporter.done = 1_000_000;
+		assert_eq!(reporter.describe(), "40% (1.0 MB of 2.5 MB)");
+		reporter.total = None;
+		assert_eq!(reporter.describe(), "1.0 MB");
+		reporter.total = Some(0);
+		assert_eq!(reporter.describe(), "1.0 MB");
+	}
+
+	#[test]
+	fn formats_bytes() {
+		assert_eq!(format_bytes(0), "0 bytes");
+		assert_eq!(format_bytes(999), "999 bytes");
+		assert_eq!(format_bytes(1_000), "1.0 KB");
+		assert_eq!(format_bytes(40_150_000), "40.1 MB");
+		assert_eq!(format_bytes(3_999_999_999), "3.9 GB");
+	}
+}
@@ -0,0 +1,185 @@
+//! Finding specific NVDA releases on the NV Access releases server.
+
+use crate::{
+	error::Error,
+	retry::{StatusError, check_status},
+	transport::{Request, Transport},
+	version::{Channel, NvdaVersion},
+};
+use anyhow::{Result, bail};
+use reqwest::{StatusCode, Url};
+use serde::Serialize;
+
+/// The public NV Access releases server.
+pub const DEFAULT_RELEASES_BASE: &str = "https://download.nvaccess.org/releases";
+
+/// The URL of the installer for a release, e.g. `<base>/2023.3.4/nvda_2023.3.4.exe`.
+#[must_use]
+pub fn installer_url(base: &str, version: &str) -> String {
+	format!("{}/{version}/nvda_{version}.exe", base.trim_end_matches('/'))
+}
+
+/// Checks that a release exists, returning the URL of its installer.
+///
+/// # Errors
+///
+/// Fails with [`Error::NotFound`] if the release doesn't exist, and fails for alpha snapshots, which can't be looked up
+/// by version.
+pub async fn find(transport: &dyn Transport, base: &str, version: &NvdaVersion) -> Result<String> {
+	if version.channel() == Channel::Alpha {
+		bail!(Error::Usage(
+			"Alpha snapshots can't be downloaded by version. Use `nvdl alpha` to get the latest one.".to_owned()
+		));
+	}
+	let url = installer_url(base, &version.to_string());
+	match check_status(transport.send(Request::head(&url)).await?) {
+		Ok(_) => Ok(url),
+		Err(StatusError { status: StatusCode::NOT_FOUND | StatusCode::FORBIDDEN, .. }) => {
+			bail!(Error::NotFound(format!("NVDA {version} does not exist on the NV Access releases server.")))
+		}
+		Err(err) => Err(err.into()),
+	}
+}
+
+/// The public NV Access alpha snapshots index.
+pub const DEFAULT_SNAPSHOTS_URL: &str = "https://download.nvaccess.org/snapshots/alpha/";
+
+/// A version of NVDA listed on the NV Access server.
+#[derive(Serialize, Debug, PartialEq, Eq)]
+pub struct Release {
+	pub version: NvdaVersion,
+	pub channel: Channel,
+	pub url: String,
+}
+
+impl Release {
+	const fn new(version: NvdaVersion, url: String) -> Self {
+		Self { channel: version.channel(), version, url }
+	}
+}
+
+/// Fetches and parses the list of releases, newest first.
+///
+/// # Errors
+///
+/// Fails if the releases index can't be fetched.
+pub async fn list(transport: &dyn Transport, base: &str) -> Result<Vec<Release>> {
+	let index = format!("{}/", base.trim_end_matches('/'));
+	let html = check_status(transport.send(Request::get(&index)).await?)?.text().await?;
+	let mut releases = parse_releases(&html, base);
+	releases.sort_by(|a, b| b.version.cmp(&a.version));
+	Ok(releases)
+}
+
+/// Fetches and parses the list of alpha snapshots, newest first.
+///
+/// # Errors
+///
+/// Fails if the snapshots index can't be fetched.
+pub async fn list_snapshots(transport: &dyn Transport, index: &str) -> Result<Vec<Release>> {
+	let html = check_status(transport.send(Request::get(index)).await?)?.text().await?;
+	let mut snapshots = parse_snapshots(&html, &Url::parse(index)?);
+	snapshots.sort_by(|a, b| b.version.cmp(&a.version));
+	Ok(snapshots)
+}
+
+/// Picks the release directories, such as `2024.2beta3/`, out of the releases index.
+fn parse_releases(html: &str, base: &str) -> Vec<Release> {
+	links(html)
+		.filter_map(|link| {
+			let name = link.strip_suffix('/')?.rsplit('/').next()?;
+			let version = name.parse::<NvdaVersion>().ok().filter(|v| v.channel() != Channel::Alpha)?;
+			Some(Release::new(version, installer_url(base, name)))
+		})
+		.collect()
+}
+
+/// Picks the snapshot installers, such as `nvda_snapshot_alpha-34213,1d9e4c2a.exe`, out of the snapshots index.
+fn parse_snapshots(html: &str, index: &Url) -> Vec<Release> {
+	links(html)
+		.filter_map(|link| {
+			let version = NvdaVersion::from_installer_name(link.rsplit('/').next()?)?;
+			let url = index.join(&link).map_or_else(|_| link.clone(), String::from);
+			(version.channel() == Channel::Alpha).then(|| Release::new(version, url))
+		})
+		.collect()
+}
+
+/// Pulls the target of every `<a href="...">` out of an HTML directory listing, percent-decoded.
+fn links(html: &str) -> impl Iterator<Item = String> + '_ {
+	html.split("href=").skip(1).filter_map(|rest| {
+		let quote = rest.chars().next().filter(|&c| c == '"' || c == '\'')?;
+		let value = &rest[1..];
+		Some(percent_decode(&value[..value.find(quote)?]))
+	})
+}
+
+fn percent_decode(s: &str) -> String {
+	let bytes = s.as_bytes();
+	let mut decoded = Vec::with_capacity(bytes.len());
+	let mut i = 0;
+	while i < bytes.len() {
+		let hex = (bytes[i] == b'%').then(|| bytes.get(i + 1..i + 3)).flatten();
+		if let Some(byte) = hex.and_then(|hex| u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok()) {
+			decoded.push(byte);
+			i += 3;
+		} else {
+			decoded.push(bytes[i]);
+			i += 1;
+		}
+	}
+	String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const BASE: &str = "https://download.nvaccess.org/releases";
+
+	#[test]
+	fn parses_releases_index() {
+		let mut releases = parse_releases(include_str!("../tests/fixtures/releases_index.html"), BASE);
+		releases.sort_by(|a, b| b.version.cmp(&a.version));
+		let versions: Vec<_> = releases.iter().map(|r| (r.version.to_string(), r.channel)).collect();
+		let versions: Vec<_> = versions.iter().map(|(v, c)| (v.as_str(), *c)).collect();
+		assert_eq!(
+			versions,
+			[
+				("2024.4.2", Channel::Stable),
+				("2024.2", Channel::Stable),
+				("2024.2rc1", Channel::Rc),
+				("2024.2beta3", Channel::Beta),
+				("2024.2beta1", Channel::Beta),
+				("2024.1", Channel::Stable),
+				("2023.3.4", Channel::Stable),
+				("2022.1", Channel::Stable),
+				("2019.3.1", Channel::Stable),
+				("2019.3", Channel::Stable),
+				("2017.3", Channel::Stable),
+			]
+		);
+		assert_eq!(releases[2].url, "https://download.nvaccess.org/releases/2024.2rc1/nvda_2024.2rc1.exe");
+	}
+
+	#[test]
+	fn parses_snapshots_index() {
+		let index = Url::parse(DEFAULT_SNAPSHOTS_URL).unwrap();
+		let mut snapshots = parse_snapshots(include_str!("../tests/fixtures/snapshots_index.html"), &index);
+		snapshots.sort_by(|a, b| b.version.cmp(&a.version));
+		let versions: Vec<_> = snapshots.iter().map(|s| s.version.to_string()).collect();
+		assert_eq!(versions, ["alpha-34250,7f0b6a11", "alpha-34213,1d9e4c2a", "alpha-34198,c04e5f93"]);
+		assert!(snapshots.iter().all(|s| s.channel == Channel::Alpha));
+		assert_eq!(
+			snapshots[1].url,
+			"https://download.nvaccess.org/snapshots/alpha/nvda_snapshot_alpha-34213,1d9e4c2a.exe"
+		);
+	}
+
+	#[test]
+	fn ignores_links_that_are_not_versions() {
+		let html =
+			r#"<a href="../">../</a><a href="stable/">stable/</a><a href="2024.1x/">x</a><a href="latest.json">"#;
+		assert!(parse_releases(html, BASE).is_empty());
+	}
+}
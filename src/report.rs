@@ -0,0 +1,159 @@
+//! Machine-readable output for scripts.
+//!
+//! The JSON and TSV formats always contain the same fields in the same order, with `null` (or an empty TSV column)
+//! for anything that doesn't apply, so scripts can rely on their shape. `version` is always a string, and empty when
+//! the version isn't known.
+
+use anyhow::Error;
+use clap::ValueEnum;
+use nvdl::{
+	checksum::{Algo, Digests, Published},
+	error::Kind,
+	lockfile::Entry,
+	version::NvdaVersion,
+};
+use serde::Serialize;
+use serde_json::json;
+use std::{path::PathBuf, time::Duration};
+
+/// How results are printed.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+	/// Human-readable text.
+	Text,
+	/// A single JSON object.
+	Json,
+	/// A header row followed by a row of tab-separated values.
+	Tsv,
+}
+
+/// Details of an installer that has been saved to disk.
+pub struct Saved {
+	pub path: PathBuf,
+	pub digests: Digests,
+	pub size: u64,
+	pub elapsed: Duration,
+}
+
+/// Everything we know about an installer, for printing in a machine-readable format.
+#[derive(Serialize)]
+pub struct Report<'a> {
+	pub channel: &'a str,
+	/// The NVDA version, or an empty string if the installer isn't named after one.
+	pub version: String,
+	pub url: &'a str,
+	pub sha1: Option<String>,
+	pub path: Option<String>,
+	pub size: Option<u64>,
+	pub elapsed_seconds: Option<f64>,
+	pub sha256: Option<String>,
+	/// Only known when SHA-512 was asked for with `--algo sha512`.
+	pub sha512: Option<String>,
+}
+
+impl<'a> Report<'a> {
+	pub fn new(channel: &'a str, version: Option<NvdaVersion>, url: &'a str, published: &Published) -> Self {
+		Self {
+			channel,
+			version: version.map(|version| version.to_string()).unwrap_or_default(),
+			url,
+			sha1: published.sha1.clone(),
+			path: None,
+			size: None,
+			elapsed_seconds: None,
+			sha256: published.sha256.clone(),
+			sha512: None,
+		}
+	}
+
+	/// A report on an installer pinned in a lockfile.
+	pub fn locked(entry: &'a Entry) -> Self {
+		Self::new(&entry.target, entry.version.clone(), &entry.url, &entry.published())
+	}
+
+	/// Records the hashes computed from the installer, keeping any that were published.
+	pub fn hashed(&mut self, digests: &Digests) {
+		for (algo, field) in
+			[(Algo::Sha1, &mut self.sha1), (Algo::Sha256, &mut self.sha256), (Algo::Sha512, &mut self.sha512)]
+		{
+			if field.is_none() {
+				*field = digests.hex(algo);
+			}
+		}
+	}
+
+	/// The hash for an algorithm in hex, if it's known.
+	pub fn hash(&self, algo: Algo) -> Option<&str> {
+		match algo {
+			Algo::Sha1 => self.sha1.as_deref(),
+			Algo::Sha256 => self.sha256.as_deref(),
+			Algo::Sha512 => self.sha512.as_deref(),
+		}
+	}
+
+	/// Records where the installer was saved, and its hashes.
+	pub fn saved(&mut self, saved: &Saved) {
+		self.hashed(&saved.digests);
+		self.path = Some(saved.path.display().to_string());
+		self.size = Some(saved.size);
+		self.elapsed_seconds = Some(saved.elapsed.as_secs_f64());
+	}
+}
+
+impl Format {
+	/// Prints a report in this format. Text reports are printed as things happen, so this does nothing for them.
+	pub fn print(self, report: &Report) {
+		match self {
+			Self::Text => {}
+			Self::Json => println!("{}", serde_json::to_string(report).unwrap_or_default()),
+			Self::Tsv => {
+				println!("{TSV_HEADER}");
+				print_tsv_row(report);
+			}
+		}
+	}
+
+	/// Prints several reports: as a JSON array, or as TSV rows under a single header.
+	pub fn print_all(self, reports: &[Report]) {
+		match self {
+			Self::Text => {}
+			Self::Json => println!("{}", serde_json::to_string(reports).unwrap_or_default()),
+			Self::Tsv => {
+				println!("{TSV_HEADER}");
+				reports.iter().for_each(print_tsv_row);
+			}
+		}
+	}
+
+	/// Prints an error as a JSON object on standard output, along with its kind and exit code, returning whether it did
+	/// so.
+	pub fn print_error(self, err: &Error, kind: Kind) -> bool {
+		if self != Self::Json {
+			return false;
+		}
+		let causes: Vec<_> = err.chain().skip(1).map(ToString::to_string).collect();
+		println!(
+			"{}",
+			json!({ "error": err.to_string(), "causes": causes, "kind": kind, "exit_code": kind.exit_code() })
+		);
+		true
+	}
+}
+
+const TSV_HEADER: &str = "channel\tversion\turl\tsha1\tpath\tsize\telapsed_seconds\tsha256\tsha512";
+
+fn print_tsv_row(report: &Report) {
+	let optional = |value: Option<String>| value.unwrap_or_default();
+	println!(
+		"{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}",
+		report.channel,
+		report.version,
+		report.url,
+		optional(report.sha1.clone()),
+		optional(report.path.clone()),
+		optional(report.size.map(|size| size.to_string())),
+		optional(report.elapsed_seconds.map(|secs| format!("{secs:.3}"))),
+		optional(report.sha256.clone()),
+		optional(report.sha512.clone()),
+	);
+}
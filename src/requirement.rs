@@ -0,0 +1,270 @@
+//! Version requirements, such as `2024.*` or `>=2023.3,<2024.1`, for picking a release from the releases index.
+
+use crate::version::{NvdaVersion, Stage};
+use std::{fmt, str::FromStr};
+
+/// A set of comparators that a version must satisfy all of.
+///
+/// Betas and release candidates only match if one of the comparators names one, so `2024.*` picks the newest stable
+/// 2024 release while `>=2024.2beta1` will also pick a release candidate. Alpha snapshots never match.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VersionReq {
+	text: String,
+	comparators: Vec<Comparator>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Comparator {
+	/// `*`, `2024.*` or `2024.1.*`: any version starting with these numbers.
+	Prefix(Vec<u16>),
+	/// `>=2023.3`, `<2024.1`, `=2024.1` and so on. A version on its own means `=`.
+	Compare(Op, NvdaVersion),
+	/// `~2024.1`: this version, or a later one with the same year and major number.
+	Tilde(NvdaVersion),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Op {
+	Exact,
+	Greater,
+	GreaterEq,
+	Less,
+	LessEq,
+}
+
+impl VersionReq {
+	/// Whether a version satisfies every comparator.
+	#[must_use]
+	pub fn matches(&self, version: &NvdaVersion) -> bool {
+		let NvdaVersion::Release { stage, .. } = version else {
+			return false;
+		};
+		if *stage != Stage::Final && !self.allows_prereleases() {
+			return false;
+		}
+		self.comparators.iter().all(|comparator| comparator.matches(version))
+	}
+
+	/// Picks up to `count` of the given versions closest to the ones this requirement asks for, newest first. Used to
+	/// suggest alternatives when nothing matches.
+	#[must_use]
+	pub fn nearest<'a>(&self, versions: &'a [NvdaVersion], count: usize) -> Vec<&'a NvdaVersion> {
+		let mut candidates: Vec<_> = versions
+			.iter()
+			.filter(|version| match version {
+				NvdaVersion::Release { stage, .. } => *stage == Stage::Final || self.allows_prereleases(),
+				NvdaVersion::Snapshot { .. } => false,
+			})
+			.collect();
+		candidates.sort();
+		candidates.dedup();
+		let split = self.comparators.first().map_or(candidates.len(), |comparator| {
+			let pivot = comparator.pivot();
+			candidates.partition_point(|version| **version < pivot)
+		});
+		let start = split.saturating_sub(count.div_ceil(2)).min(candidates.len().saturating_sub(count));
+		let mut nearest: Vec<_> = candidates.into_iter().skip(start).take(count).collect();
+		nearest.reverse();
+		nearest
+	}
+
+	fn allows_prereleases(&self) -> bool {
+		self.comparators.iter().any(|comparator| match comparator {
+			Comparator::Compare(_, NvdaVersion::Release { stage, .. })
+			| Comparator::Tilde(NvdaVersion::Release { stage, .. }) => *stage != Stage::Final,
+			_ => false,
+		})
+	}
+}
+
+impl Comparator {
+	fn matches(&self, version: &NvdaVersion) -> bool {
+		let NvdaVersion::Release { year, major, .. } = version else {
+			return false;
+		};
+		match self {
+			Self::Prefix(numbers) => numbers.iter().zip([year, major]).all(|(want, have)| want == have),
+			Self::Compare(op, bound) => match op {
+				Op::Exact => version == bound,
+				Op::Greater => version > bound,
+				Op::GreaterEq => version >= bound,
+				Op::Less => version < bound,
+				Op::LessEq => version <= bound,
+			},
+			Self::Tilde(bound) => {
+				matches!(bound, NvdaVersion::Release { year: y, major: m, .. } if y == year && m == major)
+					&& version >= bound
+			}
+		}
+	}
+
+	/// The version this comparator is centred on, for finding near misses.
+	fn pivot(&self) -> NvdaVersion {
+		match self {
+			Self::Prefix(numbers) => NvdaVersion::Release {
+				year: numbers.first().copied().unwrap_or(u16::MAX),
+				major: numbers.get(1).copied().unwrap_or(0),
+				minor: 0,
+				stage: Stage::Beta(0),
+			},
+			Self::Compare(_, version) | Self::Tilde(version) => version.clone(),
+		}
+	}
+}
+
+impl fmt::Display for VersionReq {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(&self.text)
+	}
+}
+
+/// The error returned when a string isn't a valid version requirement.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseReqError(String);
+
+impl fmt::Display for ParseReqError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{:?} is not a valid version requirement, such as 2024.*, >=2023.3,<2024.1 or ~2024.1", self.0)
+	}
+}
+
+impl std::error::Error for ParseReqError {}
+
+impl FromStr for VersionReq {
+	type Err = ParseReqError;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let comparators = s
+			.split(',')
+			.map(|part| parse_comparator(part.trim()).ok_or_else(|| ParseReqError(s.to_owned())))
+			.collect::<Result<_, _>>()?;
+		Ok(Self { text: s.trim().to_owned(), comparators })
+	}
+}
+
+fn parse_comparator(s: &str) -> Option<Comparator> {
+	if let Some(prefix) = s.strip_suffix('*') {
+		let numbers = match prefix {
+			"" => Vec::new(),
+			prefix => prefix.strip_suffix('.')?.split('.').map(|n| n.parse().ok()).collect::<Option<Vec<u16>>>()?,
+		};
+		return (numbers.len() <= 2 && prefix.bytes().all(|b| b.is_ascii_digit() || b == b'.'))
+			.then_some(Comparator::Prefix(numbers));
+	}
+	if let Some(rest) = s.strip_prefix('~') {
+		let rest = rest.trim_start();
+		if !rest.contains('.') && rest.bytes().all(|b| b.is_ascii_digit()) {
+			return Some(Comparator::Prefix(vec![rest.parse().ok()?]));
+		}
+		return release(rest).map(Comparator::Tilde);
+	}
+	let (op, rest) = [(">=", Op::GreaterEq), ("<=", Op::LessEq), (">", Op::Greater), ("<", Op::Less), ("=", Op::Exact)]
+		.into_iter()
+		.find_map(|(prefix, op)| s.strip_prefix(prefix).map(|rest| (op, rest)))
+		.unwrap_or((Op::Exact, s));
+	release(rest.trim_start()).map(|version| Comparator::Compare(op, version))
+}
+
+/// Parses a release version. Alpha snapshots aren't in the releases index, so requirements can't name them.
+fn release(s: &str) -> Option<NvdaVersion> {
+	s.parse().ok().filter(|version| matches!(version, NvdaVersion::Release { .. }))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const INDEX: [&str; 12] = [
+		"2017.3",
+		"2019.3",
+		"2019.3.1",
+		"2022.1",
+		"2023.3.4",
+		"2024.1",
+		"2024.2beta1",
+		"2024.2beta3",
+		"2024.2rc1",
+		"2024.2",
+		"2024.4.2",
+		"alpha-34213,1d9e4c2a",
+	];
+
+	fn versions() -> Vec<NvdaVersion> {
+		INDEX.iter().map(|v| v.parse().unwrap()).collect()
+	}
+
+	/// The newest version in the index matching a requirement.
+	fn newest(req: &str) -> Option<String> {
+		let req: VersionReq = req.parse().unwrap();
+		versions().into_iter().filter(|v| req.matches(v)).max().map(|v| v.to_string())
+	}
+
+	#[test]
+	fn resolves_newest_match() {
+		let cases = [
+			("*", Some("2024.4.2")),
+			("2024.*", Some("2024.4.2")),
+			("2024.2.*", Some("2024.2")),
+			("2019.*", Some("2019.3.1")),
+			("2020.*", None),
+			(">=2023.3,<2024.1", Some("2023.3.4")),
+			(">= 2023.3 , < 2024.1", Some("2023.3.4")),
+			("<2024.1", Some("2023.3.4")),
+			("<=2024.1", Some("2024.1")),
+			(">2024.4.2", None),
+			("~2024.1", Some("2024.1")),
+			("~2019.3", Some("2019.3.1")),
+			("~2024", Some("2024.4.2")),
+			("2023.3.4", Some("2023.3.4")),
+			("=2024.1.0", Some("2024.1")),
+			("2024.2beta3", Some("2024.2beta3")),
+			(">=2024.2beta1,<2024.2", Some("2024.2rc1")),
+			("~2024.2beta1", Some("2024.2")),
+			("<2024.2,>2024.1", None),
+		];
+		for (req, expected) in cases {
+			assert_eq!(newest(req).as_deref(), expected, "resolving {req:?}");
+		}
+	}
+
+	#[test]
+	fn rejects_invalid_requirements() {
+		let cases = [
+			"",
+			",",
+			"2024.*,",
+			"2024",
+			"2024*",
+			"2024.1.2.*",
+			"*.1",
+			"~",
+			"~2024.1.x",
+			">=",
+			"=>2024.1",
+			">=stable",
+			"alpha-34213,1d9e4c2a",
+			">=alpha-34213,1d9e4c2a",
+			"^2024.1",
+		];
+		for input in cases {
+			assert!(input.parse::<VersionReq>().is_err(), "{input:?} should not parse");
+		}
+	}
+
+	#[test]
+	fn suggests_nearest_versions() {
+		let cases = [
+			("2020.*", vec!["2022.1", "2019.3.1", "2019.3"]),
+			(">2024.4.2", vec!["2024.4.2", "2024.2", "2024.1"]),
+			("<2017.1", vec!["2019.3.1", "2019.3", "2017.3"]),
+			("2024.3", vec!["2024.4.2", "2024.2", "2024.1"]),
+			("2024.3rc1", vec!["2024.4.2", "2024.2", "2024.2rc1"]),
+		];
+		for (req, expected) in cases {
+			let req: VersionReq = req.parse().unwrap();
+			let versions = versions();
+			let nearest: Vec<_> = req.nearest(&versions, 3).iter().map(ToString::to_string).collect();
+			assert_eq!(nearest, expected, "nearest to {req}");
+		}
+	}
+}
@@ -0,0 +1,389 @@
+//! Working out which installer a channel, release number or version requirement refers to.
+
+use crate::{
+	api,
+	checksum::Published,
+	download::{Downloader, Event, NoProgress, Progress},
+	error::{Error, Kind},
+	lockfile::Entry,
+	releases::{self, Release},
+	requirement::VersionReq,
+	retry::Retry,
+	transport::Transport,
+	version::NvdaVersion,
+};
+use anyhow::{Context, Result, bail};
+use clap::ValueEnum;
+use nvda_url::{WIN7_HASH, WIN7_URL, XP_HASH, XP_URL};
+use std::{fmt, sync::Arc};
+
+/// Either one of the well-known NVDA versions, or a specific release.
+#[derive(Clone, Debug)]
+pub enum Target {
+	Endpoint(Endpoint),
+	/// A release number, such as `2023.3.4`.
+	Release(NvdaVersion),
+	/// The newest release matching a requirement, such as `2024.*`.
+	Match(VersionReq),
+}
+
+impl Target {
+	/// Parses a channel name, a release number or a version requirement.
+	///
+	/// # Errors
+	///
+	/// Returns a description of what was expected if the string isn't any of those.
+	pub fn parse(s: &str) -> Result<Self, String> {
+		match Endpoint::from_str(s, true) {
+			Ok(endpoint) => Ok(Self::Endpoint(endpoint)),
+			Err(_) if s.contains(['*', '<', '>', '=', '~', ',']) => {
+				s.parse().map(Self::Match).map_err(|e| e.to_string())
+			}
+			Err(_) if s.starts_with(|c: char| c.is_ascii_digit()) => {
+				s.parse().map(Self::Release).map_err(|e| e.to_string())
+			}
+			Err(_) => Err("expected stable, alpha, beta, xp, win7, or a release number such as 2023.3.4".to_owned()),
+		}
+	}
+
+	/// The channel name, or `release` for release numbers and requirements.
+	#[must_use]
+	pub const fn name(&self) -> &'static str {
+		match self {
+			Self::Endpoint(endpoint) => endpoint.name(),
+			Self::Release(_) | Self::Match(_) => "release",
+		}
+	}
+}
+
+impl fmt::Display for Target {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::Endpoint(endpoint) => f.write_str(endpoint.name()),
+			Self::Release(version) => write!(f, "{version}"),
+			Self::Match(requirement) => write!(f, "{requirement}"),
+		}
+	}
+}
+
+/// Defines the available NVDA version types that can be retrieved.
+#[derive(ValueEnum, Clone, Debug)]
+pub enum Endpoint {
+	/// Stable release version.
+	Stable,
+	/// Snapshot alpha version.
+	Alpha,
+	/// Beta release version.
+	Beta,
+	/// The last version compatible with Windows XP.
+	Xp,
+	/// The last version compatible with Windows 7.
+	Win7,
+}
+
+impl Endpoint {
+	#[must_use]
+	pub const fn name(&self) -> &'static str {
+		match self {
+			Self::Stable => "stable",
+			Self::Alpha => "alpha",
+			Self::Beta => "beta",
+			Self::Xp => "xp",
+			Self::Win7 => "win7",
+		}
+	}
+
+	/// The name of the API endpoint for this version, if it isn't pinned.
+	const fn as_channel(&self) -> Option<&'static str> {
+		match self {
+			Self::Stable | Self::Alpha | Self::Beta => Some(self.name()),
+			_ => None,
+		}
+	}
+
+	const fn as_fixed_version(&self) -> Option<(&'static str, &'static str)> {
+		match self {
+			Self::Xp => Some((XP_URL, XP_HASH)),
+			Self::Win7 => Some((WIN7_URL, WIN7_HASH)),
+			_ => None,
+		}
+	}
+}
+
+/// An installer, and the hashes it should have if they're published.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Installer {
+	pub url: String,
+	pub published: Published,
+}
+
+impl Installer {
+	/// The NVDA version, taken from the installer's file name such as `nvda_2024.4.2.exe`.
+	#[must_use]
+	pub fn version(&self) -> Option<NvdaVersion> {
+		NvdaVersion::from_installer_name(self.url.rsplit('/').next()?)
+	}
+}
+
+/// Looks up installers on the nvda.zip API and the NV Access releases server, or mirrors of them.
+#[derive(Clone)]
+pub struct Resolver {
+	transport: Arc<dyn Transport>,
+	api_base: String,
+	releases_base: String,
+	retry: Retry,
+	progress: Arc<dyn Progress>,
+}
+
+impl Resolver {
+	/// A resolver for the public servers, retrying failed requests the default number of times without reporting them.
+	#[must_use]
+	pub fn new(transport: Arc<dyn Transport>) -> Self {
+		Self {
+			transport,
+			api_base: api::DEFAULT_API_BASE.to_owned(),
+			releases_base: releases::DEFAULT_RELEASES_BASE.to_owned(),
+			retry: Retry::default(),
+			progress: Arc::new(NoProgress),
+		}
+	}
+
+	/// Uses a mirror of the nvda.zip API.
+	#[must_use]
+	pub fn api_base(mut self, base: impl Into<String>) -> Self {
+		self.api_base = base.into();
+		self
+	}
+
+	/// Uses a mirror of the NV Access releases server.
+	#[must_use]
+	pub fn releases_base(mut self, base: impl Into<String>) -> Self {
+		self.releases_base = base.into();
+		self
+	}
+
+	#[must_use]
+	pub const fn retry(mut self, retry: Retry) -> Self {
+		self.retry = retry;
+		self
+	}
+
+	/// Tells `progress` about retries, and hash lookups that failed.
+	#[must_use]
+	pub fn progress(mut self, progress: impl Progress + 'static) -> Self {
+		self.progress = Arc::new(progress);
+		self
+	}
+
+	/// Works out the installer for a version, with its hashes if they're published.
+	///
+	/// # Errors
+	///
+	/// Fails if the servers can't be reached, or the version doesn't exist.
+	pub async fn resolve(&self, target: &Target) -> Result<Installer> {
+		match target {
+			Target::Endpoint(endpoint) => {
+				if let Some((url, hash)) = endpoint.as_fixed_version() {
+					Ok(Installer {
+						url: url.to_owned(),
+						published: Published { sha1: Some(hash.to_owned()), sha256: None },
+					})
+				} else {
+					let channel = endpoint.as_channel().context("Unknown endpoint.")?;
+					let (url, published) = self
+						.retry
+						.run("Looking up the download URL", &*self.progress, || async {
+							api::get_details(&*self.transport, &self.api_base, channel)
+								.await
+								.context("Failed to retrieve download URL.")
+						})
+						.await?;
+					Ok(Installer { url, published })
+				}
+			}
+			Target::Release(version) => {
+				let url = self
+					.retry
+					.run("Looking up the release", &*self.progress, || {
+						releases::find(&*self.transport, &self.releases_base, version)
+					})
+					.await?;
+				Ok(self.with_known_hashes(url).await)
+			}
+			Target::Match(requirement) => {
+				let release = self.newest_matching(requirement).await?;
+				Ok(self.with_known_hashes(release.url).await)
+			}
+		}
+	}
+
+	/// Resolves a version to a lockfile entry, pinning both its SHA-1 and SHA-256. Installers without both published
+	/// are downloaded with `downloader` to compute them, unless `previous` already pins the same installer.
+	///
+	/// # Errors
+	///
+	/// Fails if the version can't be resolved, a published hash isn't valid, or the installer can't be downloaded or
+	/// doesn't match its published hash.
+	pub async fn lock(&self, downloader: &Downloader, target: &Target, previous: Option<&Entry>) -> Result<Entry> {
+		let installer = self.resolve(target).await?;
+		let version = installer.version();
+		let Installer { url, mut published } = installer;
+		if let Some(previous) = previous.filter(|previous| {
+			previous.url == url && published.sha1.as_ref().is_none_or(|sha1| sha1.eq_ignore_ascii_case(&previous.sha1))
+		}) {
+			published.sha1.get_or_insert_with(|| previous.sha1.clone());
+			published.sha256 = published.sha256.or_else(|| previous.sha256.clone());
+		}
+		let expected = published
+			.decode()
+			.ok_or_else(|| Error::InvalidHash(format!("The server returned an invalid hash for {target}.")))?;
+		let (sha1, sha256) = if let (Some(sha1), Some(sha256)) = (expected.sha1, expected.sha256) {
+			(sha1, sha256)
+		} else {
+			let digests = downloader.digests(&url).await?;
+			if let Some(mismatch) = digests.mismatch(&expected) {
+				bail!(Error::HashMismatch(format!(
+					"The installer at {url} doesn't match its published hash. It has {mismatch}."
+				)));
+			}
+			(digests.sha1, digests.sha256)
+		};
+		Ok(Entry {
+			target: target.to_string(),
+			version,
+			url,
+			sha1: base16ct::lower::encode_string(&sha1),
+			sha256: Some(base16ct::lower::encode_string(&sha256)),
+		})
+	}
+
+	/// Lists the releases on the releases server, newest first.
+	///
+	/// # Errors
+	///
+	/// Fails if the releases index can't be fetched.
+	pub async fn releases(&self) -> Result<Vec<Release>> {
+		self.retry
+			.run("Fetching the releases index", &*self.progress, || {
+				releases::list(&*self.transport, &self.releases_base)
+			})
+			.await
+			.context("Failed to list releases.")
+	}
+
+	/// Lists the alpha snapshots in a snapshots index, newest first.
+	///
+	/// # Errors
+	///
+	/// Fails if the snapshots index can't be fetched.
+	pub async fn snapshots(&self, index: &str) -> Result<Vec<Release>> {
+		self.retry
+			.run("Fetching the snapshots index", &*self.progress, || releases::list_snapshots(&*self.transport, index))
+			.await
+			.context("Failed to list alpha snapshots.")
+	}
+
+	/// Finds the newest release in the releases index that matches a requirement.
+	async fn newest_matching(&self, requirement: &VersionReq) -> Result<Release> {
+		let mut releases = self.releases().await?;
+		if let Some(index) = releases.iter().position(|release| requirement.matches(&release.version)) {
+			return Ok(releases.swap_remove(index));
+		}
+		let versions: Vec<_> = releases.into_iter().map(|release| release.version).collect();
+		let nearest: Vec<_> = requirement.nearest(&versions, 5).iter().map(ToString::to_string).collect();
+		if nearest.is_empty() {
+			bail!(Error::NotFound(format!(
+				"No NVDA release matches {requirement}, and the releases index doesn't list any releases."
+			)));
+		}
+		bail!(Error::NotFound(format!(
+			"No NVDA release matches {requirement}. The nearest releases are {}.",
+			nearest.join(", ")
+		)))
+	}
+
+	/// Pairs a release's URL with its hashes, if it's one we know. NV Access doesn't publish hashes next to its releases,
+	/// so only the pinned releases and whatever the stable and beta channels currently point to have them. A channel that
+	/// can't be looked up is reported to `progress` and skipped.
+	async fn with_known_hashes(&self, url: String) -> Installer {
+		let pinned = |url: &str, hash: &str| (url.to_owned(), Published { sha1: Some(hash.to_owned()), sha256: None });
+		let mut known = vec![pinned(XP_URL, XP_HASH), pinned(WIN7_URL, WIN7_HASH)];
+		for channel in ["stable", "beta"] {
+			let details = self
+				.retry
+				.run("Looking up published hashes", &*self.progress, || {
+					api::get_details(&*self.transport, &self.api_base, channel)
+				})
+				.await;
+			match details {
+				Ok(details) => known.push(details),
+				// There isn't always a beta.
+				Err(err) if Kind::of(&err) == Kind::NotFound => {}
+				// The release can still be downloaded without them, if the hash policy allows it.
+				Err(error) => self.progress.event(&Event::HashLookupFailed { channel, error: &error }),
+			}
+		}
+		let published = known.into_iter().find(|(known_url, _)| known_url.rsplit('/').next() == url.rsplit('/').next());
+		Installer { url, published: published.map(|(_, published)| published).unwrap_or_default() }
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{
+		checksum::Hasher,
+		retry::Retry,
+		testing::{Events, URL, body},
+		transport::{FakeTransport, Fault},
+	};
+	use reqwest::StatusCode;
+	use std::time::Duration;
+
+	const API: &str = "https://api.example.com";
+
+	#[tokio::test]
+	async fn locks_installers_computing_missing_hashes() {
+		let mut hasher = Hasher::new(false);
+		hasher.update(&body());
+		let digests = hasher.finalize();
+		let (sha1, sha256) =
+			(base16ct::lower::encode_string(&digests.sha1), base16ct::lower::encode_string(&digests.sha256));
+		let details = format!(r#"{{"url":"{URL}","hash":"{sha1}"}}"#);
+		let fake = Arc::new(FakeTransport::new().serve(&format!("{API}/stable.json"), details).serve(URL, body()));
+		let resolver = Resolver::new(fake.clone()).api_base(API);
+		let downloader = Downloader::new(fake.clone());
+		let stable = Target::Endpoint(Endpoint::Stable);
+
+		let entry = resolver.lock(&downloader, &stable, None).await.unwrap();
+		assert_eq!((entry.target.as_str(), entry.url.as_str()), ("stable", URL));
+		assert_eq!(entry.version, Some("2024.1".parse().unwrap()));
+		assert_eq!((entry.sha1.as_str(), entry.sha256.as_deref()), (sha1.as_str(), Some(sha256.as_str())));
+		assert_eq!(fake.requests().len(), 2, "the installer should be downloaded to compute its SHA-256");
+
+		assert_eq!(resolver.lock(&downloader, &stable, Some(&entry)).await.unwrap(), entry);
+		assert_eq!(fake.requests().len(), 3, "the previous entry's SHA-256 should be reused");
+	}
+
+	#[tokio::test]
+	async fn resolves_releases_when_hash_lookups_fail() {
+		let releases = "https://releases.example.com";
+		let url = releases::installer_url(releases, "2024.1");
+		let fake = Arc::new(
+			FakeTransport::new()
+				.serve(&url, body())
+				.fail(&format!("{API}/stable.json"), Fault::Status(StatusCode::SERVICE_UNAVAILABLE)),
+		);
+		let events = Events::default();
+		let resolver = Resolver::new(fake)
+			.api_base(API)
+			.releases_base(releases)
+			.retry(Retry::new(0, Duration::ZERO, Duration::ZERO))
+			.progress(events.clone());
+		let installer = resolver.resolve(&Target::Release("2024.1".parse().unwrap())).await.unwrap();
+		assert_eq!(installer, Installer { url, published: Published::default() });
+		let events = events.take();
+		assert_eq!(events.len(), 1, "a missing beta isn't worth mentioning: {events:?}");
+		assert!(events[0].starts_with("Couldn't look up the hashes published for stable"), "{events:?}");
+	}
+}
@@ -0,0 +1,248 @@
+//! Retrying flaky network operations with exponential backoff.
+
+use crate::{
+	download::{Event, Progress},
+	transport::{ConnectionError, Response},
+};
+use anyhow::{Chain, Error, Result};
+use reqwest::{
+	StatusCode,
+	header::{HeaderMap, RETRY_AFTER},
+};
+use std::{
+	fmt,
+	time::{Duration, SystemTime},
+};
+
+/// Retry policy for network requests.
+#[derive(Clone, Copy, Debug)]
+pub struct Retry {
+	retries: u32,
+	delay: Duration,
+	max_delay: Duration,
+}
+
+/// The longest a server can make us wait with `Retry-After`.
+pub const MAX_RETRY_AFTER: Duration = Duration::from_secs(5 * 60);
+
+/// The longest duration accepted on the command line, and the longest backoff: a day.
+const MAX_SECONDS: f64 = 86_400.0;
+
+/// Parses a number of seconds from the command line, which must be finite, not negative, and no more than a day.
+///
+/// # Errors
+///
+/// Returns a description of what was expected if it isn't.
+pub fn parse_seconds(s: &str) -> Result<f64, String> {
+	let seconds: f64 = s.parse().map_err(|_| format!("expected a number of seconds, not {s}"))?;
+	if (0.0..=MAX_SECONDS).contains(&seconds) {
+		Ok(seconds)
+	} else {
+		Err(format!("expected a number of seconds from 0 to {MAX_SECONDS}, not {s}"))
+	}
+}
+
+impl Retry {
+	/// Retries a failed request up to `retries` times, waiting `delay` before the first retry and doubling it each time,
+	/// up to `max_delay`.
+	#[must_use]
+	pub const fn new(retries: u32, delay: Duration, max_delay: Duration) -> Self {
+		Self { retries, delay, max_delay }
+	}
+
+	/// Runs `op` until it succeeds, fails with an error that retrying won't fix, or runs out of attempts, telling
+	/// `progress` about each retry.
+	///
+	/// # Errors
+	///
+	/// Returns the last error if every attempt fails.
+	pub async fn run<T, F, Fut>(&self, what: &str, progress: &dyn Progress, mut op: F) -> Result<T>
+	where
+		F: FnMut() -> Fut,
+		Fut: Future<Output = Result<T>>,
+	{
+		let mut attempt = 0;
+		loop {
+			let err = match op().await {
+				Ok(value) => return Ok(value),
+				Err(err) => err,
+			};
+			attempt += 1;
+			if attempt > self.retries || !is_transient(&err) {
+				return Err(err);
+			}
+			let delay = self.delay(&err, attempt);
+			progress.event(&Event::Retrying { what, error: &err, delay, attempt, retries: self.retries });
+			tokio::time::sleep(delay).await;
+		}
+	}
+
+	/// The delay before the given retry: as long as the server asked for, up to [`MAX_RETRY_AFTER`], or otherwise
+	/// the backoff.
+	fn delay(&self, err: &Error, attempt: u32) -> Duration {
+		retry_after(err).map_or_else(|| self.backoff(attempt), |after| after.min(MAX_RETRY_AFTER))
+	}
+
+	/// The delay before the given retry: exponential, capped, with the upper half randomised.
+	fn backoff(&self, attempt: u32) -> Duration {
+		let max_delay = self.max_delay.as_secs_f64().min(MAX_SECONDS);
+		let exponential = self.delay.as_secs_f64() * 2f64.powi(i32::try_from(attempt - 1).unwrap_or(i32::MAX));
+		let capped = exponential.min(max_delay);
+		Duration::try_from_secs_f64(capped / 2.0 + fastrand::f64() * capped / 2.0)
+			.unwrap_or_else(|_| Duration::from_secs_f64(max_delay))
+	}
+}
+
+impl Default for Retry {
+	fn default() -> Self {
+		Self::new(3, Duration::from_secs(1), Duration::from_secs(30))
+	}
+}
+
+/// An unsuccessful HTTP status, along with how long the server asked us to wait before trying again.
+#[derive(Debug)]
+pub struct StatusError {
+	pub status: StatusCode,
+	pub url: String,
+	pub retry_after: Option<Duration>,
+}
+
+impl fmt::Display for StatusError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "the server returned {} for {}", self.status, self.url)
+	}
+}
+
+impl std::error::Error for StatusError {}
+
+/// Turns an unsuccessful response into a [`StatusError`], keeping `Retry-After`.
+///
+/// # Errors
+///
+/// Returns the status if it's a client or server error.
+pub fn check_status(response: Response) -> Result<Response, StatusError> {
+	let status = response.status;
+	if status.is_client_error() || status.is_server_error() {
+		Err(StatusError { status, url: response.url, retry_after: parse_retry_after(&response.headers) })
+	} else {
+		Ok(response)
+	}
+}
+
+/// Whether an error is worth retrying: a network failure or a server error.
+///
+/// Anything we can't classify is not. Errors that don't come from the network are `nvdl`'s own, such as a release
+/// that doesn't exist or a response that isn't what we expected, and would fail the same way every time.
+#[must_use]
+pub fn is_transient(err: &Error) -> bool {
+	for cause in err.chain() {
+		if let Some(err) = cause.downcast_ref::<StatusError>() {
+			return is_transient_status(err.status);
+		}
+		if let Some(err) = cause.downcast_ref::<reqwest::Error>() {
+			return match err.status() {
+				Some(status) => is_transient_status(status),
+				// Either the connection dropped partway through the body, or the body was malformed JSON.
+				None if err.is_decode() => !Chain::new(err).any(<dyn std::error::Error>::is::<serde_json::Error>),
+				None => err.is_connect() || err.is_timeout() || err.is_request() || err.is_body(),
+			};
+		}
+		if cause.is::<ConnectionError>() {
+			return true;
+		}
+		if cause.is::<std::io::Error>() {
+			return false;
+		}
+	}
+	false
+}
+
+fn is_transient_status(status: StatusCode) -> bool {
+	status.is_server_error() || matches!(status, StatusCode::REQUEST_TIMEOUT | StatusCode::TOO_MANY_REQUESTS)
+}
+
+fn retry_after(err: &Error) -> Option<Duration> {
+	err.chain().find_map(|cause| cause.downcast_ref::<StatusError>()).and_then(|err| err.retry_after)
+}
+
+/// Parses a `Retry-After` header, which is either a number of seconds or an HTTP date.
+fn parse_retry_after(headers: &HeaderMap) -> Option<Duration> {
+	let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+	value.parse().map(Duration::from_secs).ok().or_else(|| {
+		let date = httpdate::parse_http_date(value).ok()?;
+		Some(date.duration_since(SystemTime::now()).unwrap_or_default())
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use reqwest::header::HeaderValue;
+
+	fn retry_after_header(value: &str) -> HeaderMap {
+		HeaderMap::from_iter([(RETRY_AFTER, HeaderValue::from_str(value).unwrap())])
+	}
+
+	fn status_error(status: StatusCode, retry_after: Option<Duration>) -> Error {
+		StatusError { status, url: "https://example.com".to_owned(), retry_after }.into()
+	}
+
+	#[test]
+	fn backs_off_exponentially_up_to_the_maximum() {
+		let retry = Retry::new(10, Duration::from_secs(1), Duration::from_secs(30));
+		for (attempt, low, high) in
+			[(1, 0.5, 1.0), (2, 1.0, 2.0), (4, 4.0, 8.0), (10, 15.0, 30.0), (u32::MAX, 15.0, 30.0)]
+		{
+			let delay = retry.backoff(attempt).as_secs_f64();
+			assert!((low..=high).contains(&delay), "retry {attempt} waited {delay}");
+		}
+		let retry = Retry::new(10, Duration::from_secs(86_400), Duration::from_secs(86_400));
+		assert!(retry.backoff(u32::MAX) <= Duration::from_secs(86_400));
+	}
+
+	#[test]
+	fn honours_retry_after_up_to_a_ceiling() {
+		let retry = Retry::new(3, Duration::from_secs(1), Duration::from_secs(30));
+		let asked = status_error(StatusCode::SERVICE_UNAVAILABLE, Some(Duration::from_secs(120)));
+		assert_eq!(retry.delay(&asked, 1), Duration::from_secs(120));
+		let greedy = status_error(StatusCode::SERVICE_UNAVAILABLE, Some(Duration::from_secs(99_999_999)));
+		assert_eq!(retry.delay(&greedy, 1), MAX_RETRY_AFTER);
+	}
+
+	#[test]
+	fn parses_retry_after() {
+		assert_eq!(parse_retry_after(&retry_after_header("120")), Some(Duration::from_secs(120)));
+		assert_eq!(parse_retry_after(&retry_after_header("Wed, 21 Oct 2015 07:28:00 GMT")), Some(Duration::ZERO));
+		assert_eq!(parse_retry_after(&retry_after_header("soon")), None);
+		assert_eq!(parse_retry_after(&HeaderMap::new()), None);
+	}
+
+	#[test]
+	fn parses_seconds() {
+		assert_eq!(parse_seconds("0"), Ok(0.0));
+		assert_eq!(parse_seconds("2.5"), Ok(2.5));
+		assert_eq!(parse_seconds("86400"), Ok(86_400.0));
+		for bad in ["-1", "inf", "NaN", "1e20", "86401", "soon"] {
+			assert!(parse_seconds(bad).is_err(), "{bad}");
+		}
+	}
+
+	#[test]
+	fn retries_network_and_server_errors() {
+		assert!(is_transient(&status_error(StatusCode::SERVICE_UNAVAILABLE, None)));
+		assert!(is_transient(&status_error(StatusCode::TOO_MANY_REQUESTS, None)));
+		assert!(!is_transient(&status_error(StatusCode::NOT_FOUND, None)));
+		assert!(is_transient(
+			&Error::new(ConnectionError("reset".to_owned())).context("The download was interrupted.")
+		));
+		assert!(!is_transient(&Error::new(std::io::Error::other("disk full"))));
+	}
+
+	#[test]
+	fn doesnt_retry_unclassified_errors() {
+		assert!(!is_transient(&anyhow::anyhow!("No download URL in the response.")));
+		assert!(!is_transient(&crate::error::Error::NotFound("NVDA 2099.1 doesn't exist.".to_owned()).into()));
+		let json = serde_json::from_str::<u8>("{").unwrap_err();
+		assert!(!is_transient(&Error::new(json).context("https://example.com did not return the expected JSON.")));
+	}
+}
@@ -0,0 +1,168 @@
+//! Checksum manifests in the format of coreutils' `sha1sum` and `sha256sum`, such as `SHA256SUMS`.
+//!
+//! Each line is a hex hash, a space, a mode character (a space for text or `*` for binary), and a file name. Names
+//! containing a backslash or newline are escaped, with a backslash at the start of the line to say so.
+
+use crate::{
+	checksum::{Algo, Digests},
+	download::write_replacing,
+};
+use anyhow::{Context, Result};
+use std::{fs, io::ErrorKind, path::Path};
+
+/// An entry in a checksum manifest.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Line {
+	pub hash: String,
+	pub filename: String,
+}
+
+impl Line {
+	/// The algorithm that produced this hash, going by its length.
+	#[must_use]
+	pub const fn algo(&self) -> Option<Algo> {
+		match self.hash.len() {
+			40 => Some(Algo::Sha1),
+			64 => Some(Algo::Sha256),
+			128 => Some(Algo::Sha512),
+			_ => None,
+		}
+	}
+}
+
+/// The manifest file name for an algorithm.
+const fn manifest_name(algo: Algo) -> &'static str {
+	match algo {
+		Algo::Sha1 => "SHA1SUMS",
+		Algo::Sha256 => "SHA256SUMS",
+		Algo::Sha512 => "SHA512SUMS",
+	}
+}
+
+/// Records a file's hashes in the manifests next to it, replacing any existing entries for it.
+///
+/// `SHA512SUMS` is only written if the SHA-512 was computed. Each manifest is replaced in one step, so a failed write
+/// leaves the old one intact.
+///
+/// # Errors
+///
+/// Fails if a manifest can't be read or written, or isn't valid.
+pub fn record(path: &Path, digests: &Digests) -> Result<()> {
+	let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or_else(|| Path::new("."));
+	let filename = path.file_name().context("The installer has no file name.")?.to_string_lossy();
+	for algo in [Algo::Sha1, Algo::Sha256, Algo::Sha512] {
+		let Some(hash) = digests.hex(algo) else {
+			continue;
+		};
+		let manifest = dir.join(manifest_name(algo));
+		let text = match fs::read_to_string(&manifest) {
+			Ok(text) => text,
+			Err(err) if err.kind() == ErrorKind::NotFound => String::new(),
+			Err(err) => return Err(err).with_context(|| format!("Failed to read {}.", manifest.display())),
+		};
+		let mut lines = parse(&text).with_context(|| format!("Failed to update {}.", manifest.display()))?;
+		lines.retain(|line| line.filename != filename);
+		lines.push(Line { hash, filename: filename.clone().into_owned() });
+		write_replacing(&manifest, format(&lines).as_bytes())
+			.with_context(|| format!("Failed to write {}.", manifest.display()))?;
+	}
+	Ok(())
+}
+
+/// Parses a manifest, skipping blank lines.
+///
+/// # Errors
+///
+/// Fails on the first line that isn't a valid checksum line.
+pub fn parse(text: &str) -> Result<Vec<Line>> {
+	text.lines()
+		.enumerate()
+		.filter(|(_, line)| !line.trim().is_empty())
+		.map(|(i, line)| parse_line(line).with_context(|| format!("Line {} is not a valid checksum line.", i + 1)))
+		.collect()
+}
+
+fn parse_line(line: &str) -> Option<Line> {
+	let (escaped, line) = line.strip_prefix('\\').map_or((false, line), |line| (true, line));
+	let (hash, rest) = line.split_once(' ')?;
+	let filename = rest.strip_prefix([' ', '*'])?;
+	if hash.is_empty() || !hash.bytes().all(|b| b.is_ascii_hexdigit()) || filename.is_empty() {
+		return None;
+	}
+	let filename = if escaped { unescape(filename)? } else { filename.to_owned() };
+	Some(Line { hash: hash.to_ascii_lowercase(), filename })
+}
+
+fn format(lines: &[Line]) -> String {
+	lines
+		.iter()
+		.map(|line| {
+			if line.filename.contains(['\\', '\n']) {
+				let filename = line.filename.replace('\\', "\\\\").replace('\n', "\\n");
+				format!("\\{}  {filename}\n", line.hash)
+			} else {
+				format!("{}  {}\n", line.hash, line.filename)
+			}
+		})
+		.collect()
+}
+
+fn unescape(s: &str) -> Option<String> {
+	let mut unescaped = String::with_capacity(s.len());
+	let mut chars = s.chars();
+	while let Some(c) = chars.next() {
+		unescaped.push(match c {
+			'\\' => match chars.next()? {
+				'\\' => '\\',
+				'n' => '\n',
+				_ => return None,
+			},
+			c => c,
+		});
+	}
+	Some(unescaped)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::testing::temp_dir;
+
+	#[test]
+	fn parses_coreutils_output() {
+		let text = "4a4b937d24de6e4cf9503c77619257c27bbba310  nvda_2024.1.exe\n\
+			C1F2A3B4C5D6E7F8A9B0C1D2E3F4A5B6C7D8E9F0 *nvda 2023.3.exe\n\
+			\n\
+			\\4a4b937d24de6e4cf9503c77619257c27bbba310  odd\\\\name\\n.exe\n";
+		let lines = parse(text).unwrap();
+		let filenames: Vec<_> = lines.iter().map(|line| line.filename.as_str()).collect();
+		assert_eq!(filenames, ["nvda_2024.1.exe", "nvda 2023.3.exe", "odd\\name\n.exe"]);
+		assert_eq!(lines[1].hash, "c1f2a3b4c5d6e7f8a9b0c1d2e3f4a5b6c7d8e9f0");
+		assert_eq!(parse(&format(&lines)).unwrap(), lines);
+	}
+
+	#[test]
+	fn rejects_malformed_lines() {
+		for text in ["4a4b937d", "4a4b937d nvda.exe", "xyz  nvda.exe", "4a4b937d  ", "\\4a4b937d  bad\\escape"] {
+			assert!(parse(text).is_err(), "{text:?} should not parse");
+		}
+	}
+
+	#[test]
+	fn records_hashes_replacing_old_entries() {
+		let dir = temp_dir();
+		let other = "c1f2a3b4c5d6e7f8a9b0c1d2e3f4a5b6c7d8e9f0  other.exe\n";
+		fs::write(dir.join("SHA1SUMS"), format!("{other}{}  nvda.exe\n", "0".repeat(40))).unwrap();
+		let digests = Digests { sha1: [0xab; 20], sha256: [0xcd; 32], sha512: None };
+		record(&dir.join("nvda.exe"), &digests).unwrap();
+
+		let sha1 = fs::read_to_string(dir.join("SHA1SUMS")).unwrap();
+		assert_eq!(sha1, format!("{other}{}  nvda.exe\n", "ab".repeat(20)));
+		let sha256 = fs::read_to_string(dir.join("SHA256SUMS")).unwrap();
+		assert_eq!(sha256, format!("{}  nvda.exe\n", "cd".repeat(32)));
+		let mut files: Vec<_> = fs::read_dir(&dir).unwrap().map(|entry| entry.unwrap().file_name()).collect();
+		files.sort();
+		assert_eq!(files, ["SHA1SUMS", "SHA256SUMS"], "the temporary files should be gone");
+		fs::remove_dir_all(&dir).unwrap();
+	}
+}
@@ -0,0 +1,55 @@
+//! Fixtures shared by the unit tests.
+
+use crate::{
+	checksum::{Hasher, Published},
+	download::{Event, NoProgress, Progress, Transfer},
+	resolve::Installer,
+};
+use std::{
+	path::PathBuf,
+	sync::{Arc, Mutex},
+};
+
+/// Where the test installer is served from.
+pub const URL: &str = "https://example.com/nvda_2024.1.exe";
+
+/// The bytes of a test installer, long enough to be split into chunks and segments.
+pub fn body() -> Vec<u8> {
+	(0..100_000u32).map(|i| (i % 251) as u8).collect()
+}
+
+/// An installer at [`URL`] whose published SHA-256 is that of `body`.
+pub fn installer(body: &[u8]) -> Installer {
+	let mut hasher = Hasher::new(false);
+	hasher.update(body);
+	let sha256 = base16ct::lower::encode_string(&hasher.finalize().sha256);
+	Installer { url: URL.to_owned(), published: Published { sha1: None, sha256: Some(sha256) } }
+}
+
+/// A fresh, empty temporary directory.
+pub fn temp_dir() -> PathBuf {
+	let dir = std::env::temp_dir().join(format!("nvdl-test-{}", fastrand::u64(..)));
+	std::fs::create_dir_all(&dir).unwrap();
+	dir
+}
+
+/// Records the events it's told about, as the sentences they display as.
+#[derive(Clone, Default)]
+pub struct Events(Arc<Mutex<Vec<String>>>);
+
+impl Events {
+	/// The events recorded since the last call.
+	pub fn take(&self) -> Vec<String> {
+		std::mem::take(&mut self.0.lock().unwrap())
+	}
+}
+
+impl Progress for Events {
+	fn start(&self, _total: Option<u64>, _done: u64) -> Box<dyn Transfer> {
+		Box::new(NoProgress)
+	}
+
+	fn event(&self, event: &Event<'_>) {
+		self.0.lock().unwrap().push(event.to_string());
+	}
+}
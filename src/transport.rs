@@ -0,0 +1,529 @@
+//! The HTTP requests `nvdl` makes, behind a trait so they can be served by something other than the network.
+//!
+//! Everything goes through [`Transport::send`]: API lookups are a GET whose body is read as JSON, release checks are a
+//! HEAD, and downloads are a GET whose body is streamed with [`Response::chunk`]. [`HttpTransport`] sends requests over
+//! the network with reqwest, and [`FakeTransport`] answers them from memory, for tests.
+
+use crate::error::Error;
+use anyhow::{Context, Result};
+use bytes::Bytes;
+use reqwest::{
+	Certificate, Client, Method, NoProxy, Proxy, StatusCode,
+	header::{ACCEPT_RANGES, CONTENT_LENGTH, CONTENT_RANGE, ETAG, HeaderMap, HeaderName, HeaderValue, IF_RANGE, RANGE},
+};
+use serde::de::DeserializeOwned;
+use std::{
+	collections::{HashMap, VecDeque},
+	fs,
+	path::PathBuf,
+	pin::Pin,
+	sync::{Arc, Mutex, PoisonError},
+	time::Duration,
+};
+
+/// A future that can be sent between threads, as returned by the transport traits.
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// Sends HTTP requests.
+pub trait Transport: Send + Sync {
+	/// Sends a request, returning the response as soon as its headers have arrived.
+	fn send(&self, request: Request) -> BoxFuture<'_, Result<Response>>;
+}
+
+impl<T: Transport + ?Sized> Transport for Arc<T> {
+	fn send(&self, request: Request) -> BoxFuture<'_, Result<Response>> {
+		(**self).send(request)
+	}
+}
+
+/// The body of a response, which arrives a chunk at a time.
+pub trait Body: Send {
+	/// The next chunk of the body, or `None` at the end.
+	fn chunk(&mut self) -> BoxFuture<'_, Result<Option<Bytes>>>;
+}
+
+/// A request that failed before a complete response arrived, such as a refused connection or a body that was cut
+/// short. These are worth retrying.
+#[derive(Debug, thiserror::Error)]
+#[error("{0}")]
+pub struct ConnectionError(pub String);
+
+/// An HTTP request.
+#[derive(Clone, Debug)]
+pub struct Request {
+	pub method: Method,
+	pub url: String,
+	pub headers: HeaderMap,
+}
+
+impl Request {
+	#[must_use]
+	pub fn get(url: impl Into<String>) -> Self {
+		Self { method: Method::GET, url: url.into(), headers: HeaderMap::new() }
+	}
+
+	#[must_use]
+	pub fn head(url: impl Into<String>) -> Self {
+		Self { method: Method::HEAD, ..Self::get(url) }
+	}
+
+	/// Adds a header. Values that aren't valid in a header are left out.
+	#[must_use]
+	pub fn header(mut self, name: HeaderName, value: &str) -> Self {
+		if let Ok(value) = HeaderValue::from_str(value) {
+			self.headers.insert(name, value);
+		}
+		self
+	}
+
+	pub(crate) fn header_str(&self, name: HeaderName) -> Option<&str> {
+		self.headers.get(name).and_then(|value| value.to_str().ok())
+	}
+}
+
+/// An HTTP response, whose body hasn't been read yet.
+pub struct Response {
+	pub status: StatusCode,
+	/// The URL the response came from, after any redirects.
+	pub url: String,
+	pub headers: HeaderMap,
+	body: Box<dyn Body>,
+}
+
+impl Response {
+	#[must_use]
+	pub fn new(status: StatusCode, url: impl Into<String>, headers: HeaderMap, body: Box<dyn Body>) -> Self {
+		Self { status, url: url.into(), headers, body }
+	}
+
+	/// A header's value, if it's present and valid text.
+	#[must_use]
+	pub fn header(&self, name: HeaderName) -> Option<&str> {
+		self.headers.get(name).and_then(|value| value.to_str().ok())
+	}
+
+	/// The length of the body, if the server said.
+	#[must_use]
+	pub fn content_length(&self) -> Option<u64> {
+		self.header(CONTENT_LENGTH)?.trim().parse().ok()
+	}
+
+	/// The next chunk of the body, or `None` at the end.
+	///
+	/// # Errors
+	///
+	/// Fails if the body can't be read, such as when the connection drops.
+	pub async fn chunk(&mut self) -> Result<Option<Bytes>> {
+		self.body.chunk().await
+	}
+
+	/// Reads the whole body.
+	///
+	/// # Errors
+	///
+	/// Fails if the body can't be read.
+	pub async fn bytes(mut self) -> Result<Vec<u8>> {
+		let mut bytes = Vec::new();
+		while let Some(chunk) = self.chunk().await? {
+			bytes.extend_from_slice(&chunk);
+		}
+		Ok(bytes)
+	}
+
+	/// Reads the whole body as text.
+	///
+	/// # Errors
+	///
+	/// Fails if the body can't be read.
+	pub async fn text(self) -> Result<String> {
+		Ok(String::from_utf8_lossy(&self.bytes().await?).into_owned())
+	}
+
+	/// Reads the whole body as JSON.
+	///
+	/// # Errors
+	///
+	/// Fails if the body can't be read, or isn't the expected JSON.
+	pub async fn json<T: DeserializeOwned>(self) -> Result<T> {
+		let url = self.url.clone();
+		serde_json::from_slice(&self.bytes().await?).with_context(|| format!("{url} did not return the expected JSON."))
+	}
+}
+
+/// Sends requests over the network with reqwest.
+#[derive(Clone)]
+pub struct HttpTransport {
+	client: Client,
+	/// The timeouts the client was built with, to explain timeout errors.
+	connect_timeout: Option<Duration>,
+	read_timeout: Option<Duration>,
+}
+
+impl HttpTransport {
+	#[must_use]
+	pub const fn new(client: Client) -> Self {
+		Self { client, connect_timeout: None, read_timeout: None }
+	}
+
+	/// A transport with a client set up for a proxy, extra root certificates, timeouts and so on.
+	///
+	/// # Errors
+	///
+	/// Fails if the proxy URL isn't valid, or a CA certificate can't be read.
+	pub fn with_options(options: &HttpOptions) -> Result<Self> {
+		Ok(Self {
+			client: options.client()?,
+			connect_timeout: options.connect_timeout,
+			read_timeout: options.read_timeout,
+		})
+	}
+
+	/// Explains a request that timed out, keeping reqwest's error as the cause.
+	fn explain(&self, err: reqwest::Error, url: &str) -> anyhow::Error {
+		if !err.is_timeout() {
+			return err.into();
+		}
+		let message = if err.is_connect() {
+			format!("Couldn't connect to {}{}.", host(url), in_seconds("within", self.connect_timeout))
+		} else {
+			format!("{} stopped sending data{}.", host(url), in_seconds("for", self.read_timeout))
+		};
+		anyhow::Error::new(err).context(message)
+	}
+}
+
+impl Default for HttpTransport {
+	/// A transport with the default [`HttpOptions`].
+	fn default() -> Self {
+		Self::with_options(&HttpOptions::default()).expect("the built-in root certificates are valid")
+	}
+}
+
+/// How to reach servers: through which proxy, and trusting which certificates.
+///
+/// Without a proxy, the `HTTPS_PROXY`, `HTTP_PROXY`, `ALL_PROXY` and `NO_PROXY` environment variables are honoured.
+/// Servers are trusted if their certificates chain to one of the Mozilla root certificates built into `nvdl`.
+#[derive(Clone, Debug)]
+pub struct HttpOptions {
+	proxy: Option<String>,
+	ca_certs: Vec<PathBuf>,
+	system_certs: bool,
+	insecure: bool,
+	connect_timeout: Option<Duration>,
+	read_timeout: Option<Duration>,
+}
+
+impl Default for HttpOptions {
+	/// No proxy or extra certificates, giving up connecting after 30 seconds and on silent responses after 60.
+	fn default() -> Self {
+		Self {
+			proxy: None,
+			ca_certs: Vec::new(),
+			system_certs: false,
+			insecure: false,
+			connect_timeout: Some(Duration::from_secs(30)),
+			read_timeout: Some(Duration::from_secs(60)),
+		}
+	}
+}
+
+impl HttpOptions {
+	#[must_use]
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Sends requests through a proxy, except to hosts in `NO_PROXY`.
+	#[must_use]
+	pub fn proxy(mut self, url: impl Into<String>) -> Self {
+		self.proxy = Some(url.into());
+		self
+	}
+
+	/// Also trusts the certificates in a PEM file.
+	#[must_use]
+	pub fn ca_cert(mut self, path: impl Into<PathBuf>) -> Self {
+		self.ca_certs.push(path.into());
+		self
+	}
+
+	/// Trusts the operating system's certificate store rather than the built-in root certificates.
+	#[must_use]
+	pub const fn system_certs(mut self, system_certs: bool) -> Self {
+		self.system_certs = system_certs;
+		self
+	}
+
+	/// Turns off certificate checks.
+	#[must_use]
+	pub const fn insecure(mut self, insecure: bool) -> Self {
+		self.insecure = insecure;
+		self
+	}
+
+	/// Gives up connecting to a server after a while. `None` waits forever.
+	#[must_use]
+	pub const fn connect_timeout(mut self, timeout: Option<Duration>) -> Self {
+		self.connect_timeout = timeout;
+		self
+	}
+
+	/// Gives up on a response if no data arrives for a while. `None` waits forever.
+	#[must_use]
+	pub const fn read_timeout(mut self, timeout: Option<Duration>) -> Self {
+		self.read_timeout = timeout;
+		self
+	}
+
+	/// Whether certificate checks are off.
+	#[must_use]
+	pub const fn is_insecure(&self) -> bool {
+		self.insecure
+	}
+
+	fn client(&self) -> Result<Client> {
+		let mut builder = Client::builder();
+		if let Some(timeout) = self.connect_timeout {
+			builder = builder.connect_timeout(timeout);
+		}
+		if let Some(timeout) = self.read_timeout {
+			builder = builder.read_timeout(timeout);
+		}
+		if let Some(url) = &self.proxy {
+			let proxy =
+				Proxy::all(url).map_err(|err| Error::Usage(format!("{url} isn't a valid proxy URL: {err}.")))?;
+			builder = builder.proxy(proxy.no_proxy(NoProxy::from_env()));
+		}
+		let mut extra = Vec::new();
+		for path in &self.ca_certs {
+			let pem =
+				fs::read(path).with_context(|| format!("Failed to read the CA certificate {}.", path.display()))?;
+			let certs = Certificate::from_pem_bundle(&pem).unwrap_or_default();
+			if certs.is_empty() {
+				return Err(Error::Usage(format!("{} doesn't contain any PEM certificates.", path.display())).into());
+			}
+			extra.extend(certs);
+		}
+		builder = if self.system_certs {
+			builder.tls_certs_merge(extra)
+		} else {
+			let bundled = webpki_root_certs::TLS_SERVER_ROOT_CERTS.iter().map(|der| Certificate::from_der(der));
+			builder.tls_certs_only(bundled.collect::<reqwest::Result<Vec<_>>>()?.into_iter().chain(extra))
+		};
+		Ok(builder.tls_danger_accept_invalid_certs(self.insecure).build()?)
+	}
+}
+
+impl Transport for HttpTransport {
+	fn send(&self, request: Request) -> BoxFuture<'_, Result<Response>> {
+		Box::pin(async move {
+			let response = self
+				.client
+				.request(request.method, &request.url)
+				.headers(request.headers)
+				.send()
+				.await
+				.map_err(|err| self.explain(err, &request.url))?;
+			let (status, url, headers) = (response.status(), response.url().to_string(), response.headers().clone());
+			let body = HttpBody { response, transport: self.clone(), url: url.clone() };
+			Ok(Response::new(status, url, headers, Box::new(body)))
+		})
+	}
+}
+
+/// The body of a reqwest response, with timeouts explained.
+struct HttpBody {
+	response: reqwest::Response,
+	transport: HttpTransport,
+	url: String,
+}
+
+impl Body for HttpBody {
+	fn chunk(&mut self) -> BoxFuture<'_, Result<Option<Bytes>>> {
+		Box::pin(async move { self.response.chunk().await.map_err(|err| self.transport.explain(err, &self.url)) })
+	}
+}
+
+/// Describes how long a timeout was, such as ` for 60 seconds`, if it's known.
+fn in_seconds(preposition: &str, timeout: Option<Duration>) -> String {
+	let Some(seconds) = timeout.map(|timeout| timeout.as_secs_f64()) else {
+		return String::new();
+	};
+	format!(" {preposition} {seconds} {}", if (seconds - 1.0).abs() < f64::EPSILON { "second" } else { "seconds" })
+}
+
+/// The host a URL points to, for messages.
+fn host(url: &str) -> &str {
+	let rest = url.split_once("://").map_or(url, |(_, rest)| rest);
+	rest.split(['/', '?', '#']).next().unwrap_or(rest)
+}
+
+/// Something to go wrong with the next request for a URL on a [`FakeTransport`].
+#[derive(Clone, Copy, Debug)]
+pub enum Fault {
+	/// Fail to connect.
+	Refuse,
+	/// Respond with an error status.
+	Status(StatusCode),
+	/// Drop the connection after sending this many bytes of the body.
+	Disconnect(usize),
+}
+
+/// A transport that answers requests from memory, for tests. Unknown URLs get a 404.
+///
+/// Range requests, from an offset to the end or for a span of bytes, are honoured for bodies served with an `ETag`, as
+/// long as the `If-Range` header matches it. Every request is recorded, so tests can check what was sent.
+#[derive(Default)]
+pub struct FakeTransport {
+	routes: Mutex<HashMap<String, Route>>,
+	requests: Mutex<Vec<Request>>,
+}
+
+#[derive(Default)]
+struct Route {
+	body: Bytes,
+	etag: Option<String>,
+	/// Advertise ranges, but always send the whole body.
+	ignore_ranges: bool,
+	faults: VecDeque<Fault>,
+}
+
+impl FakeTransport {
+	#[must_use]
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Serves a body at a URL.
+	#[must_use]
+	pub fn serve(self, url: &str, body: impl Into<Bytes>) -> Self {
+		self.route(url, |route| route.body = body.into());
+		self
+	}
+
+	/// Serves a body at a URL with an `ETag`, so downloads of it can be resumed or split into ranges.
+	#[must_use]
+	pub fn serve_resumable(self, url: &str, body: impl Into<Bytes>, etag: &str) -> Self {
+		self.route(url, |route| {
+			route.body = body.into();
+			route.etag = Some(etag.to_owned());
+		});
+		self
+	}
+
+	/// Serves a body at a URL with an `ETag`, advertising support for ranges but always sending the whole body, like a
+	/// server or proxy that ignores `Range` headers.
+	#[must_use]
+	pub fn serve_ignoring_ranges(self, url: &str, body: impl Into<Bytes>, etag: &str) -> Self {
+		self.route(url, |route| {
+			route.body = body.into();
+			route.etag = Some(etag.to_owned());
+			route.ignore_ranges = true;
+		});
+		self
+	}
+
+	/// Makes the next request for a URL go wrong. Faults are used up in the order they're added.
+	#[must_use]
+	pub fn fail(self, url: &str, fault: Fault) -> Self {
+		self.route(url, |route| route.faults.push_back(fault));
+		self
+	}
+
+	/// The requests sent so far.
+	#[must_use]
+	pub fn requests(&self) -> Vec<Request> {
+		self.requests.lock().unwrap_or_else(PoisonError::into_inner).clone()
+	}
+
+	fn route(&self, url: &str, update: impl FnOnce(&mut Route)) {
+		update(self.routes.lock().unwrap_or_else(PoisonError::into_inner).entry(url.to_owned()).or_default());
+	}
+
+	fn respond(&self, request: &Request) -> Result<Response> {
+		let route = self
+			.routes
+			.lock()
+			.unwrap_or_else(PoisonError::into_inner)
+			.get_mut(&request.url)
+			.map(|route| (route.body.clone(), route.etag.clone(), route.ignore_ranges, route.faults.pop_front()));
+		let mut headers = HeaderMap::new();
+		let Some((mut body, etag, ignore_ranges, fault)) = route else {
+			return Ok(Response::new(StatusCode::NOT_FOUND, &request.url, headers, Box::new(Chunks::default())));
+		};
+		let mut status = StatusCode::OK;
+		let mut cut = None;
+		match fault {
+			Some(Fault::Refuse) => return Err(ConnectionError(format!("connection to {} refused", request.url)).into()),
+			Some(Fault::Status(fault)) => {
+				return Ok(Response::new(fault, &request.url, headers, Box::new(Chunks::default())));
+			}
+			Some(Fault::Disconnect(after)) => cut = Some(after),
+			None => {}
+		}
+		if let Some(etag) = &etag {
+			headers.insert(ETAG, HeaderValue::from_str(etag)?);
+			headers.insert(ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+			if let Some((start, end)) = request.header_str(RANGE).and_then(|range| parse_range(range, body.len()))
+				&& request.header_str(IF_RANGE) == Some(etag.as_str())
+				&& !ignore_ranges
+			{
+				headers.insert(CONTENT_RANGE, HeaderValue::from_str(&format!("bytes {start}-{end}/{}", body.len()))?);
+				status = StatusCode::PARTIAL_CONTENT;
+				body = body.slice(start..=end);
+			}
+		}
+		headers.insert(CONTENT_LENGTH, HeaderValue::from(body.len()));
+		if request.method == Method::HEAD {
+			body = Bytes::new();
+		}
+		let chunks = Chunks::new(&body, cut);
+		Ok(Response::new(status, &request.url, headers, Box::new(chunks)))
+	}
+}
+
+impl Transport for FakeTransport {
+	fn send(&self, request: Request) -> BoxFuture<'_, Result<Response>> {
+		let response = self.respond(&request);
+		self.requests.lock().unwrap_or_else(PoisonError::into_inner).push(request);
+		Box::pin(async move { response })
+	}
+}
+
+/// A body held in memory, sent in small chunks, optionally failing partway through.
+#[derive(Default)]
+struct Chunks {
+	chunks: VecDeque<Bytes>,
+	fail: bool,
+}
+
+impl Chunks {
+	const SIZE: usize = 16 * 1024;
+
+	fn new(body: &Bytes, cut: Option<usize>) -> Self {
+		let sent = cut.map_or(body.len(), |cut| cut.min(body.len()));
+		let chunks =
+			(0..sent).step_by(Self::SIZE).map(|start| body.slice(start..sent.min(start + Self::SIZE))).collect();
+		Self { chunks, fail: cut.is_some() }
+	}
+}
+
+impl Body for Chunks {
+	fn chunk(&mut self) -> BoxFuture<'_, Result<Option<Bytes>>> {
+		let chunk = match self.chunks.pop_front() {
+			Some(chunk) => Ok(Some(chunk)),
+			None if self.fail => Err(ConnectionError("the connection was reset".to_owned()).into()),
+			None => Ok(None),
+		};
+		Box::pin(async move { chunk })
+	}
+}
+
+/// Parses a `Range: bytes=<start>-[<end>]` header into the first and last byte it asks for, if they're in a body of
+/// length `len`.
+fn parse_range(value: &str, len: usize) -> Option<(usize, usize)> {
+	let (start, end) = value.strip_prefix("bytes=")?.split_once('-')?;
+	let start = start.parse().ok()?;
+	let end = if end.is_empty() { len.checked_sub(1)? } else { end.parse::<usize>().ok()?.min(len.checked_sub(1)?) };
+	(start <= end).then_some((start, end))
+}
@@ -0,0 +1,191 @@
+//! Checking installers on disk against the hashes they should have.
+
+use crate::{
+	checksum::{Algo, Digests, Expected, Hasher},
+	download::{self, Downloader},
+	error::Error,
+	resolve::Installer,
+	sums,
+};
+use anyhow::{Context, Result, bail};
+use std::path::Path;
+
+/// Checks installers against their official hashes.
+pub struct Verifier<'a> {
+	downloader: &'a Downloader,
+}
+
+/// The outcome of checking a file.
+#[derive(Debug)]
+pub struct Verification {
+	/// The hashes of the file.
+	pub digests: Digests,
+	/// The first expected hash the file doesn't match, described for humans. `None` if it's intact.
+	pub mismatch: Option<String>,
+}
+
+impl Verification {
+	#[must_use]
+	pub const fn is_intact(&self) -> bool {
+		self.mismatch.is_none()
+	}
+}
+
+/// The outcome of checking a file listed in a checksum manifest such as `SHA256SUMS`.
+#[derive(Debug)]
+pub struct Listed {
+	/// The file name, as listed.
+	pub filename: String,
+	pub algo: Algo,
+	/// The listed hash, in lowercase hex.
+	pub expected: String,
+	/// The hash of the file in lowercase hex, or `None` if it couldn't be read.
+	pub actual: Option<String>,
+}
+
+impl Listed {
+	#[must_use]
+	pub fn matches(&self) -> bool {
+		self.actual.as_deref() == Some(self.expected.as_str())
+	}
+}
+
+impl<'a> Verifier<'a> {
+	/// A verifier that hashes files, and downloads installers whose hashes aren't published, with a downloader.
+	#[must_use]
+	pub const fn new(downloader: &'a Downloader) -> Self {
+		Self { downloader }
+	}
+
+	/// The hashes an installer should have: the published ones, or if there aren't any, the hashes of the installer
+	/// itself, downloaded from its official URL.
+	///
+	/// # Errors
+	///
+	/// Fails if a published hash isn't valid, or the installer can't be downloaded.
+	pub async fn expected(&self, installer: &Installer) -> Result<Expected> {
+		let published = &installer.published;
+		if published.is_empty() {
+			let digests = self.downloader.digests(&installer.url).await?;
+			return Ok(Expected { sha1: Some(digests.sha1), sha256: Some(digests.sha256) });
+		}
+		Ok(published
+			.decode()
+			.ok_or_else(|| Error::InvalidHash(format!("The server returned an invalid hash for {}.", installer.url)))?)
+	}
+
+	/// Hashes a file and compares it with the hashes it should have.
+	///
+	/// # Errors
+	///
+	/// Fails if the file can't be read.
+	pub async fn verify(&self, path: &Path, expected: &Expected) -> Result<Verification> {
+		let digests =
+			self.downloader.hash_file(path).await.with_context(|| format!("Failed to read {}.", path.display()))?;
+		Ok(Verification { mismatch: digests.mismatch(expected), digests })
+	}
+
+	/// Hashes every file listed in a checksum manifest, like `sha256sum -c`. Names are relative to the current
+	/// directory. Files that are missing or can't be read are reported as not matching.
+	///
+	/// # Errors
+	///
+	/// Fails if the manifest can't be read, isn't valid, doesn't list any files, or has a hash of an unknown length.
+	pub async fn check_sums(&self, manifest: &Path) -> Result<Vec<Listed>> {
+		let text = tokio::fs::read_to_string(manifest)
+			.await
+			.with_context(|| format!("Failed to read {}.", manifest.display()))?;
+		let lines =
+			sums::parse(&text).with_context(|| format!("{} is not a valid checksum file.", manifest.display()))?;
+		if lines.is_empty() {
+			bail!("{} doesn't list any files.", manifest.display());
+		}
+		let mut listed = Vec::with_capacity(lines.len());
+		for line in lines {
+			let algo = line
+				.algo()
+				.with_context(|| format!("Can't tell which algorithm the hash for {} is.", line.filename))?;
+			let actual = download::hash_file(Path::new(&line.filename), Hasher::new(algo == Algo::Sha512))
+				.await
+				.ok()
+				.and_then(|digests| digests.hex(algo));
+			listed.push(Listed { filename: line.filename, algo, expected: line.hash, actual });
+		}
+		Ok(listed)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{
+		checksum::Published,
+		testing::{URL, body, installer, temp_dir},
+		transport::FakeTransport,
+	};
+	use std::sync::Arc;
+
+	async fn check(fake: &Arc<FakeTransport>, installer: &Installer, contents: &[u8]) -> Verification {
+		let downloader = Downloader::new(fake.clone());
+		let verifier = Verifier::new(&downloader);
+		let path = temp_dir().join("nvda.exe");
+		std::fs::write(&path, contents).unwrap();
+		let verification = verifier.verify(&path, &verifier.expected(installer).await.unwrap()).await.unwrap();
+		std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+		verification
+	}
+
+	#[tokio::test]
+	async fn accepts_intact_files() {
+		let fake = Arc::new(FakeTransport::new());
+		let installer = installer(&body());
+		let verification = check(&fake, &installer, &body()).await;
+		assert!(verification.is_intact(), "{:?}", verification.mismatch);
+		assert!(fake.requests().is_empty(), "published hashes shouldn't need a download");
+	}
+
+	#[tokio::test]
+	async fn detects_mismatches() {
+		let fake = Arc::new(FakeTransport::new());
+		let installer = installer(&body());
+		let mut tampered = body();
+		tampered[50_000] ^= 1;
+		let verification = check(&fake, &installer, &tampered).await;
+		let mismatch = verification.mismatch.as_deref();
+		assert!(mismatch.is_some_and(|mismatch| mismatch.starts_with("SHA-256 ")), "{mismatch:?}");
+	}
+
+	#[tokio::test]
+	async fn downloads_installers_without_published_hashes() {
+		let fake = Arc::new(FakeTransport::new().serve(URL, body()));
+		let installer = Installer { url: URL.to_owned(), published: Published::default() };
+		assert!(check(&fake, &installer, &body()).await.is_intact());
+		assert_eq!(fake.requests().len(), 1);
+
+		let fake = Arc::new(FakeTransport::new().serve(URL, body()));
+		let mut tampered = body();
+		tampered.truncate(50_000);
+		assert!(!check(&fake, &installer, &tampered).await.is_intact());
+	}
+
+	#[tokio::test]
+	async fn checks_listed_files() {
+		let dir = temp_dir();
+		let (intact, tampered) = (dir.join("intact.exe"), dir.join("tampered.exe"));
+		std::fs::write(&intact, body()).unwrap();
+		std::fs::write(&tampered, &body()[1..]).unwrap();
+		let hash = installer(&body()).published.sha256.unwrap();
+		let manifest = dir.join("SHA256SUMS");
+		let listing = [&intact, &tampered, &dir.join("missing.exe")].map(|path| format!("{hash}  {}", path.display()));
+		std::fs::write(&manifest, listing.join("\n")).unwrap();
+
+		let downloader = Downloader::new(Arc::new(FakeTransport::new()));
+		let listed = Verifier::new(&downloader).check_sums(&manifest).await.unwrap();
+		let matches: Vec<_> = listed.iter().map(|file| (file.algo, file.matches(), file.actual.is_some())).collect();
+		assert_eq!(matches, [(Algo::Sha256, true, true), (Algo::Sha256, false, true), (Algo::Sha256, false, false)]);
+
+		std::fs::write(&manifest, "").unwrap();
+		assert!(Verifier::new(&downloader).check_sums(&manifest).await.is_err());
+		std::fs::remove_dir_all(&dir).unwrap();
+	}
+}
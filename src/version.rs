@@ -0,0 +1,329 @@
+//! NVDA version numbers.
+//!
+//! Releases are numbered `YEAR.MAJOR[.MINOR]`, with betas and release candidates marked by a suffix such as
+//! `2024.2beta3` or `2024.2rc1`. Alpha snapshots are identified by a build number and a commit hash instead, as in
+//! `alpha-34213,1d9e4c2a`.
+
+use clap::ValueEnum;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::{cmp::Ordering, fmt, str::FromStr};
+
+/// A parsed NVDA version.
+///
+/// Versions are totally ordered: releases by number, with betas before release candidates before the final release,
+/// and alpha snapshots by build number. Snapshots are built from the development branch, so they count as newer than
+/// every release.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum NvdaVersion {
+	/// A stable, beta or release candidate version.
+	Release { year: u16, major: u16, minor: u16, stage: Stage },
+	/// An alpha snapshot.
+	Snapshot { build: u64, commit: String },
+}
+
+/// How far through the release process a version is. Ordered from earliest to latest.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Stage {
+	Beta(u16),
+	Rc(u16),
+	Final,
+}
+
+/// The channel an NVDA version was published on.
+#[derive(ValueEnum, Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Channel {
+	/// Full releases.
+	Stable,
+	/// Beta releases.
+	Beta,
+	/// Release candidates.
+	Rc,
+	/// Alpha snapshots.
+	Alpha,
+}
+
+impl Channel {
+	#[must_use]
+	pub const fn name(self) -> &'static str {
+		match self {
+			Self::Stable => "stable",
+			Self::Beta => "beta",
+			Self::Rc => "rc",
+			Self::Alpha => "alpha",
+		}
+	}
+}
+
+impl NvdaVersion {
+	/// The channel this version was published on.
+	#[must_use]
+	pub const fn channel(&self) -> Channel {
+		match self {
+			Self::Release { stage: Stage::Final, .. } => Channel::Stable,
+			Self::Release { stage: Stage::Beta(_), .. } => Channel::Beta,
+			Self::Release { stage: Stage::Rc(_), .. } => Channel::Rc,
+			Self::Snapshot { .. } => Channel::Alpha,
+		}
+	}
+
+	/// Recovers the version from an installer filename such as `nvda_2024.1.exe` or
+	/// `nvda_snapshot_alpha-34213,1d9e4c2a.exe`.
+	#[must_use]
+	pub fn from_installer_name(filename: &str) -> Option<Self> {
+		let stem = filename.strip_suffix(".exe")?;
+		stem.strip_prefix("nvda_snapshot_").or_else(|| stem.strip_prefix("nvda_"))?.parse().ok()
+	}
+}
+
+impl Ord for NvdaVersion {
+	fn cmp(&self, other: &Self) -> Ordering {
+		match (self, other) {
+			(
+				Self::Release { year, major, minor, stage },
+				Self::Release { year: other_year, major: other_major, minor: other_minor, stage: other_stage },
+			) => (year, major, minor, stage).cmp(&(other_year, other_major, other_minor, other_stage)),
+			(Self::Snapshot { build, commit }, Self::Snapshot { build: other_build, commit: other_commit }) => {
+				(build, commit).cmp(&(other_build, other_commit))
+			}
+			(Self::Release { .. }, Self::Snapshot { .. }) => Ordering::Less,
+			(Self::Snapshot { .. }, Self::Release { .. }) => Ordering::Greater,
+		}
+	}
+}
+
+impl PartialOrd for NvdaVersion {
+	fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+		Some(self.cmp(other))
+	}
+}
+
+impl fmt::Display for NvdaVersion {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::Release { year, major, minor, stage } => {
+				write!(f, "{year}.{major}")?;
+				if *minor != 0 {
+					write!(f, ".{minor}")?;
+				}
+				match stage {
+					Stage::Beta(n) => write!(f, "beta{n}"),
+					Stage::Rc(n) => write!(f, "rc{n}"),
+					Stage::Final => Ok(()),
+				}
+			}
+			Self::Snapshot { build, commit } => write!(f, "alpha-{build},{commit}"),
+		}
+	}
+}
+
+/// The error returned when a string isn't a valid NVDA version.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseVersionError(String);
+
+impl fmt::Display for ParseVersionError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{:?} is not a valid NVDA version, such as 2024.1, 2024.2beta3 or alpha-34213,1d9e4c2a", self.0)
+	}
+}
+
+impl std::error::Error for ParseVersionError {}
+
+impl FromStr for NvdaVersion {
+	type Err = ParseVersionError;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let err = || ParseVersionError(s.to_owned());
+		if let Some(rest) = s.strip_prefix("alpha-") {
+			let (build, commit) = rest.split_once(',').ok_or_else(err)?;
+			if commit.is_empty() || !commit.bytes().all(|b| b.is_ascii_hexdigit()) {
+				return Err(err());
+			}
+			return Ok(Self::Snapshot { build: parse_number(build).ok_or_else(err)?, commit: commit.to_owned() });
+		}
+		let split = s.find(|c: char| c.is_ascii_alphabetic()).unwrap_or(s.len());
+		let (numbers, suffix) = s.split_at(split);
+		let stage = if suffix.is_empty() {
+			Stage::Final
+		} else if let Some(n) = suffix.strip_prefix("beta") {
+			Stage::Beta(parse_number(n).ok_or_else(err)?)
+		} else if let Some(n) = suffix.strip_prefix("rc") {
+			Stage::Rc(parse_number(n).ok_or_else(err)?)
+		} else {
+			return Err(err());
+		};
+		let numbers = numbers.split('.').map(parse_number).collect::<Option<Vec<u16>>>().ok_or_else(err)?;
+		match numbers[..] {
+			[year, major] => Ok(Self::Release { year, major, minor: 0, stage }),
+			[year, major, minor] => Ok(Self::Release { year, major, minor, stage }),
+			_ => Err(err()),
+		}
+	}
+}
+
+/// Parses a number made only of ASCII digits, unlike [`str::parse`], which also accepts a leading `+`.
+fn parse_number<T: FromStr>(s: &str) -> Option<T> {
+	if s.is_empty() || !s.bytes().all(|b| b.is_ascii_digit()) {
+		return None;
+	}
+	s.parse().ok()
+}
+
+impl Serialize for NvdaVersion {
+	fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		serializer.collect_str(self)
+	}
+}
+
+impl<'de> Deserialize<'de> for NvdaVersion {
+	fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+		String::deserialize(deserializer)?.parse().map_err(serde::de::Error::custom)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn release(year: u16, major: u16, minor: u16, stage: Stage) -> NvdaVersion {
+		NvdaVersion::Release { year, major, minor, stage }
+	}
+
+	fn snapshot(build: u64, commit: &str) -> NvdaVersion {
+		NvdaVersion::Snapshot { build, commit: commit.to_owned() }
+	}
+
+	#[test]
+	fn parses_valid_versions() {
+		let cases = [
+			("2024.1", release(2024, 1, 0, Stage::Final)),
+			("2024.4.2", release(2024, 4, 2, Stage::Final)),
+			("2017.3", release(2017, 3, 0, Stage::Final)),
+			("2024.2beta3", release(2024, 2, 0, Stage::Beta(3))),
+			("2024.2rc1", release(2024, 2, 0, Stage::Rc(1))),
+			("2019.2.1beta1", release(2019, 2, 1, Stage::Beta(1))),
+			("2024.1.0", release(2024, 1, 0, Stage::Final)),
+			("alpha-34213,1d9e4c2a", snapshot(34213, "1d9e4c2a")),
+			("alpha-7,ABCDEF12", snapshot(7, "ABCDEF12")),
+		];
+		for (input, expected) in cases {
+			assert_eq!(input.parse::<NvdaVersion>(), Ok(expected), "parsing {input:?}");
+		}
+	}
+
+	#[test]
+	fn rejects_invalid_versions() {
+		let cases = [
+			"",
+			"2024",
+			"2024.",
+			".1",
+			"2024..1",
+			"2024.1.2.3",
+			"2024.+1",
+			"2024.1beta",
+			"2024.1rc",
+			"2024.1alpha1",
+			"2024.1beta1rc1",
+			"2024.1 ",
+			"v2024.1",
+			"stable",
+			"alpha",
+			"alpha-",
+			"alpha-123",
+			"alpha-123,",
+			"alpha-,abc",
+			"alpha-12x,abc",
+			"alpha-123,xyz",
+			"99999.1",
+		];
+		for input in cases {
+			assert!(input.parse::<NvdaVersion>().is_err(), "{input:?} should not parse");
+		}
+	}
+
+	#[test]
+	fn displays_canonical_form() {
+		let cases = [
+			("2024.1", "2024.1"),
+			("2024.1.0", "2024.1"),
+			("2024.4.2", "2024.4.2"),
+			("2024.2beta3", "2024.2beta3"),
+			("2024.2rc1", "2024.2rc1"),
+			("2019.2.1beta1", "2019.2.1beta1"),
+			("alpha-34213,1d9e4c2a", "alpha-34213,1d9e4c2a"),
+		];
+		for (input, expected) in cases {
+			assert_eq!(input.parse::<NvdaVersion>().unwrap().to_string(), expected);
+		}
+	}
+
+	#[test]
+	fn orders_versions() {
+		let ascending = [
+			"2017.3",
+			"2019.3",
+			"2019.3.1",
+			"2023.3.4",
+			"2024.1",
+			"2024.2beta1",
+			"2024.2beta3",
+			"2024.2beta10",
+			"2024.2rc1",
+			"2024.2rc2",
+			"2024.2",
+			"2024.2.1",
+			"2024.10",
+			"2025.1beta1",
+			"alpha-34198,c04e5f93",
+			"alpha-34213,1d9e4c2a",
+			"alpha-34250,7f0b6a11",
+		];
+		let versions: Vec<NvdaVersion> = ascending.iter().map(|v| v.parse().unwrap()).collect();
+		for pair in versions.windows(2) {
+			assert!(pair[0] < pair[1], "{} should sort before {}", pair[0], pair[1]);
+		}
+		let mut shuffled = versions.clone();
+		shuffled.reverse();
+		shuffled.swap(2, 9);
+		shuffled.sort();
+		assert_eq!(shuffled, versions);
+	}
+
+	#[test]
+	fn equal_versions_compare_equal() {
+		let cases = [("2024.1", "2024.1.0"), ("alpha-1,ab", "alpha-1,ab")];
+		for (a, b) in cases {
+			assert_eq!(a.parse::<NvdaVersion>().unwrap().cmp(&b.parse().unwrap()), Ordering::Equal, "{a} vs {b}");
+		}
+	}
+
+	#[test]
+	fn reports_channels() {
+		let cases = [
+			("2024.1", Channel::Stable),
+			("2024.2beta3", Channel::Beta),
+			("2024.2rc1", Channel::Rc),
+			("alpha-34213,1d9e4c2a", Channel::Alpha),
+		];
+		for (input, expected) in cases {
+			assert_eq!(input.parse::<NvdaVersion>().unwrap().channel(), expected, "channel of {input}");
+		}
+	}
+
+	#[test]
+	fn recovers_versions_from_installer_names() {
+		let cases = [
+			("nvda_2024.4.2.exe", Some("2024.4.2")),
+			("nvda_2024.2beta3.exe", Some("2024.2beta3")),
+			("nvda_snapshot_alpha-34213,1d9e4c2a.exe", Some("alpha-34213,1d9e4c2a")),
+			("nvda_installer.exe", None),
+			("nvda_2024.1.zip", None),
+			("2024.1.exe", None),
+		];
+		for (filename, expected) in cases {
+			let expected = expected.map(|v| v.parse().unwrap());
+			assert_eq!(NvdaVersion::from_installer_name(filename), expected, "version of {filename}");
+		}
+	}
+}
@@ -0,0 +1,513 @@
+//! Runs the `nvdl` binary against a local mock of the nvda.zip API and the installers it points to.
+
+use sha1::{Digest, Sha1};
+use std::{
+	collections::HashMap,
+	fs,
+	io::{BufRead, BufReader, Write},
+	net::{TcpListener, TcpStream},
+	path::{Path, PathBuf},
+	process::{Command, Output},
+	sync::{Arc, Mutex},
+	thread,
+	time::{Duration, Instant},
+};
+
+/// A canned response.
+#[derive(Clone)]
+struct Route {
+	status: u16,
+	body: Vec<u8>,
+	/// Close the connection after sending this many bytes of the body, although the full length was promised.
+	truncate: Option<usize>,
+	/// After sending the truncated body, keep the connection open without sending anything more, rather than closing it.
+	stall: bool,
+	/// Honour `Range` requests guarded by this `If-Range` validator. Ranges are never truncated.
+	etag: Option<&'static str>,
+}
+
+impl Route {
+	fn ok(body: impl Into<Vec<u8>>) -> Self {
+		Self { status: 200, body: body.into(), truncate: None, stall: false, etag: None }
+	}
+
+	fn not_found() -> Self {
+		Self { status: 404, body: b"Not found".to_vec(), truncate: None, stall: false, etag: None }
+	}
+}
+
+/// A mock server on a random local port, answering each request on its own connection.
+struct Server {
+	base: String,
+	/// The method and path of every request so far.
+	requests: Arc<Mutex<Vec<String>>>,
+}
+
+impl Server {
+	/// Starts a server with the routes built for its base URL, such as `http://127.0.0.1:1234`.
+	fn start(routes: impl FnOnce(&str) -> HashMap<String, Route>) -> Self {
+		let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+		let base = format!("http://{}", listener.local_addr().unwrap());
+		let routes = Arc::new(routes(&base));
+		let requests = Arc::new(Mutex::new(Vec::new()));
+		let log = requests.clone();
+		thread::spawn(move || {
+			for stream in listener.incoming().flatten() {
+				let (routes, log) = (routes.clone(), log.clone());
+				thread::spawn(move || respond(stream, &routes, &log));
+			}
+		});
+		Self { base, requests }
+	}
+
+	/// A server whose `/stable.json` publishes an installer with the given SHA-1, served by `route`.
+	fn stable(route: Route, sha1: &str) -> Self {
+		Self::start(|base| {
+			let details = format!(r#"{{"url":"{base}{INSTALLER}","hash":"{sha1}"}}"#);
+			HashMap::from([("/stable.json".to_owned(), Route::ok(details)), (INSTALLER.to_owned(), route)])
+		})
+	}
+
+	fn installer_url(&self) -> String {
+		format!("{}{INSTALLER}", self.base)
+	}
+
+	/// How many times a request line such as `GET /stable.json` has been received.
+	fn count(&self, request: &str) -> usize {
+		self.requests.lock().unwrap().iter().filter(|line| *line == request).count()
+	}
+}
+
+fn respond(mut stream: TcpStream, routes: &HashMap<String, Route>, log: &Mutex<Vec<String>>) {
+	let mut reader = BufReader::new(stream.try_clone().unwrap());
+	let mut request_line = String::new();
+	let _ = reader.read_line(&mut request_line);
+	let mut headers = HashMap::new();
+	let mut line = String::new();
+	while reader.read_line(&mut line).is_ok_and(|n| n > 2) {
+		if let Some((name, value)) = line.split_once(':') {
+			headers.insert(name.trim().to_ascii_lowercase(), value.trim().to_owned());
+		}
+		line.clear();
+	}
+	let mut parts = request_line.split_whitespace();
+	let (method, mut path) = (parts.next().unwrap_or_default(), parts.next().unwrap_or_default());
+	// Requests sent to a proxy name the whole URL.
+	if let Some(rest) = path.strip_prefix("http://") {
+		path = rest.find('/').map_or("/", |start| &rest[start..]);
+	}
+	log.lock().unwrap().push(format!("{method} {path}"));
+	let mut route = routes.get(path).cloned().unwrap_or_else(Route::not_found);
+	let mut extra = String::new();
+	if let Some(etag) = route.etag {
+		extra = format!("ETag: {etag}\r\nAccept-Ranges: bytes\r\n");
+		let start =
+			headers.get("range").and_then(|range| range.strip_prefix("bytes=")?.strip_suffix('-')?.parse().ok());
+		if let Some(start) = start.filter(|_| headers.get("if-range").map(String::as_str) == Some(etag)) {
+			extra += &format!("Content-Range: bytes {start}-{}/{}\r\n", route.body.len() - 1, route.body.len());
+			route = Route { status: 206, body: route.body[start..].to_vec(), truncate: None, ..route };
+		}
+	}
+	let head = format!(
+		"HTTP/1.1 {} Mock\r\nContent-Length: {}\r\n{extra}Connection: close\r\n\r\n",
+		route.status,
+		route.body.len()
+	);
+	let _ = stream.write_all(head.as_bytes());
+	if method != "HEAD" {
+		let _ = stream.write_all(&route.body[..route.truncate.unwrap_or(route.body.len())]);
+	}
+	let _ = stream.flush();
+	if route.stall {
+		thread::sleep(Duration::from_secs(10));
+	}
+}
+
+const INSTALLER: &str = "/files/nvda_2024.4.2.exe";
+
+fn installer() -> Vec<u8> {
+	(0..200_000u32).map(|i| (i * 7 % 256) as u8).collect()
+}
+
+fn sha1(bytes: &[u8]) -> String {
+	base16ct::lower::encode_string(&Sha1::digest(bytes))
+}
+
+/// Runs `nvdl` in a fresh directory, returning its output and the directory.
+fn nvdl(server: &Server, args: &[&str]) -> (Output, PathBuf) {
+	let dir = std::env::temp_dir().join(format!("nvdl-cli-{}", fastrand::u64(..)));
+	fs::create_dir_all(&dir).unwrap();
+	(nvdl_in(&dir, server, args), dir)
+}
+
+/// Runs `nvdl` in an existing directory.
+fn nvdl_in(dir: &Path, server: &Server, args: &[&str]) -> Output {
+	Command::new(env!("CARGO_BIN_EXE_nvdl"))
+		.args(["--api-base", &server.base, "--retries", "0", "--progress", "none"])
+		.args(args)
+		.current_dir(dir)
+		.env_remove("NVDL_API_BASE")
+		.env_remove("NVDL_RELEASES_BASE")
+		.env_remove("RUST_BACKTRACE")
+		.output()
+		.unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+	String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+fn stderr(output: &Output) -> String {
+	String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+/// The files left in a directory, sorted, after removing it.
+fn files_in(dir: &Path) -> Vec<String> {
+	let mut files: Vec<_> =
+		fs::read_dir(dir).unwrap().map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned()).collect();
+	files.sort();
+	fs::remove_dir_all(dir).unwrap();
+	files
+}
+
+#[test]
+fn prints_url() {
+	let server = Server::stable(Route::ok(installer()), &sha1(&installer()));
+	let (output, dir) = nvdl(&server, &["--url"]);
+	assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+	assert_eq!(stdout(&output), format!("{}\n", server.installer_url()));
+	assert!(files_in(&dir).is_empty());
+}
+
+#[test]
+fn reports_usage_errors_as_json() {
+	let server = Server::stable(Route::ok(installer()), &sha1(&installer()));
+	let (output, dir) = nvdl(&server, &["--format", "json", "--segments", "99"]);
+	assert_eq!(output.status.code(), Some(2), "{}", stderr(&output));
+	let error: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+	assert_eq!(error["kind"], "usage");
+	assert_eq!(error["exit_code"], 2);
+	assert!(error["error"].as_str().unwrap().contains("--segments"), "{error}");
+	assert!(files_in(&dir).is_empty());
+
+	let (output, dir) = nvdl(&server, &["--segments", "99"]);
+	assert_eq!(output.status.code(), Some(2));
+	assert!(output.stdout.is_empty());
+	assert!(stderr(&output).contains("--segments"));
+	assert!(files_in(&dir).is_empty());
+}
+
+#[test]
+fn prints_checksum() {
+	let server = Server::stable(Route::ok(installer()), &sha1(&installer()));
+	let (output, dir) = nvdl(&server, &["--checksum"]);
+	assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+	assert_eq!(stdout(&output), format!("{}\n", sha1(&installer())));
+	assert!(files_in(&dir).is_empty());
+}
+
+#[test]
+fn prints_url_and_checksum() {
+	let server = Server::stable(Route::ok(installer()), &sha1(&installer()));
+	let (output, dir) = nvdl(&server, &["--url", "--checksum"]);
+	assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+	assert_eq!(stdout(&output), format!("{} ({})\n", server.installer_url(), sha1(&installer())));
+	files_in(&dir);
+
+	let (output, dir) = nvdl(&server, &["--url", "--checksum", "--format", "json"]);
+	let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+	assert_eq!(report["url"], server.installer_url());
+	assert_eq!(report["sha1"], sha1(&installer()));
+	assert_eq!(report["version"], "2024.4.2");
+	assert_eq!(report["path"], serde_json::Value::Null);
+	files_in(&dir);
+}
+
+#[test]
+fn downloads_installer_with_good_hash() {
+	let server = Server::stable(Route::ok(installer()), &sha1(&installer()));
+	let (output, dir) = nvdl(&server, &[]);
+	assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+	assert_eq!(stdout(&output), "Saved the installer to nvda_2024.4.2.exe.\n");
+	assert_eq!(fs::read(dir.join("nvda_2024.4.2.exe")).unwrap(), installer());
+	assert_eq!(files_in(&dir), ["nvda_2024.4.2.exe"]);
+}
+
+#[test]
+fn skips_installer_already_present() {
+	let server = Server::stable(Route::ok(installer()), &sha1(&installer()));
+	let (output, dir) = nvdl(&server, &[]);
+	assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+	assert_eq!(server.count(&format!("GET {INSTALLER}")), 1);
+
+	let output = nvdl_in(&dir, &server, &[]);
+	assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+	assert_eq!(stdout(&output), "nvda_2024.4.2.exe is already up to date.\n");
+	assert_eq!(server.count(&format!("GET {INSTALLER}")), 1, "the installer shouldn't be downloaded again");
+
+	let output = nvdl_in(&dir, &server, &["--force"]);
+	assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+	assert_eq!(stdout(&output), "Saved the installer to nvda_2024.4.2.exe.\n");
+	assert_eq!(server.count(&format!("GET {INSTALLER}")), 2);
+	assert_eq!(files_in(&dir), ["nvda_2024.4.2.exe"]);
+}
+
+#[test]
+fn deletes_installer_with_bad_hash() {
+	let server = Server::stable(Route::ok(installer()), &sha1(b"something else"));
+	let (output, dir) = nvdl(&server, &[]);
+	assert_eq!(output.status.code(), Some(3), "{}", stderr(&output));
+	assert!(stderr(&output).contains("doesn't match its published hash, so it was deleted"), "{}", stderr(&output));
+	assert!(files_in(&dir).is_empty());
+
+	let (output, dir) = nvdl(&server, &["--on-hash-mismatch", "keep"]);
+	assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+	assert!(stderr(&output).contains("Warning: the installer doesn't match"), "{}", stderr(&output));
+	assert_eq!(files_in(&dir), ["nvda_2024.4.2.exe"]);
+}
+
+#[test]
+fn refuses_malformed_hash() {
+	let server = Server::stable(Route::ok(installer()), "not-a-hash");
+	let (output, dir) = nvdl(&server, &["--format", "json"]);
+	assert_eq!(output.status.code(), Some(4), "{}", stderr(&output));
+	let error: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+	assert_eq!(error["kind"], "invalid_hash");
+	assert_eq!(error["exit_code"], 4);
+	assert!(files_in(&dir).is_empty());
+}
+
+#[test]
+fn reports_missing_installer() {
+	let server = Server::stable(Route::not_found(), &sha1(&installer()));
+	let (output, dir) = nvdl(&server, &[]);
+	assert_eq!(output.status.code(), Some(5), "{}", stderr(&output));
+	assert!(stderr(&output).contains("404"), "{}", stderr(&output));
+	assert!(files_in(&dir).is_empty());
+
+	let (output, dir) = nvdl(&server, &["beta", "--url"]);
+	assert_eq!(output.status.code(), Some(5), "{}", stderr(&output));
+	assert!(stderr(&output).contains("Failed to retrieve download URL."), "{}", stderr(&output));
+	files_in(&dir);
+}
+
+#[test]
+fn reports_truncated_download() {
+	let route = Route { truncate: Some(50_000), ..Route::ok(installer()) };
+	let server = Server::stable(route, &sha1(&installer()));
+	let (output, dir) = nvdl(&server, &[]);
+	assert_eq!(output.status.code(), Some(6), "{}", stderr(&output));
+	assert!(stderr(&output).contains("The download was interrupted."), "{}", stderr(&output));
+	assert!(files_in(&dir).is_empty(), "a download that can't be resumed shouldn't be kept");
+}
+
+#[test]
+fn refuses_unverifiable_release() {
+	let server = Server::start(|base| {
+		let details = format!(r#"{{"url":"{base}{INSTALLER}","hash":"{}"}}"#, sha1(&installer()));
+		HashMap::from([
+			("/stable.json".to_owned(), Route::ok(details)),
+			("/releases/2024.1/nvda_2024.1.exe".to_owned(), Route::ok(installer())),
+		])
+	});
+	let releases = format!("{}/releases", server.base);
+	let (output, dir) = nvdl(&server, &["2024.1", "--releases-base", &releases]);
+	assert_eq!(output.status.code(), Some(4), "{}", stderr(&output));
+	assert!(stderr(&output).contains("No hash is published for this version"), "{}", stderr(&output));
+	assert!(files_in(&dir).is_empty());
+
+	let (output, dir) = nvdl(&server, &["2024.1", "--releases-base", &releases, "--on-invalid-hash", "skip-verify"]);
+	assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+	assert!(stderr(&output).contains("so the download can't be verified"), "{}", stderr(&output));
+	assert_eq!(files_in(&dir), ["nvda_2024.1.exe"]);
+}
+
+#[test]
+fn reports_verify_mismatch_as_one_json_document() {
+	let server = Server::stable(Route::ok(installer()), &sha1(&installer()));
+	let dir = std::env::temp_dir().join(format!("nvdl-cli-{}", fastrand::u64(..)));
+	fs::create_dir_all(&dir).unwrap();
+	fs::write(dir.join("nvda.exe"), installer()).unwrap();
+
+	let output = nvdl_in(&dir, &server, &["verify", "nvda.exe", "--sha1", &sha1(&installer()), "--format", "json"]);
+	assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+	let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+	assert_eq!(report["matches"], true);
+
+	let output = nvdl_in(&dir, &server, &["verify", "nvda.exe", "--sha1", &"0".repeat(40), "--format", "json"]);
+	assert_eq!(output.status.code(), Some(3), "{}", stderr(&output));
+	let error: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+	assert_eq!(error["kind"], "hash_mismatch");
+	assert!(error["error"].as_str().unwrap().contains(&sha1(&installer())), "{error}");
+
+	let output = nvdl_in(&dir, &server, &["verify", "nvda.exe", "--sha1", &"0".repeat(40), "--format", "tsv"]);
+	assert_eq!(output.status.code(), Some(3), "{}", stderr(&output));
+	assert_eq!(stdout(&output), "");
+	assert_eq!(files_in(&dir), ["nvda.exe"]);
+}
+
+#[test]
+fn reports_failed_sums_as_one_json_document() {
+	let server = Server::stable(Route::ok(installer()), &sha1(&installer()));
+	let dir = std::env::temp_dir().join(format!("nvdl-cli-{}", fastrand::u64(..)));
+	fs::create_dir_all(&dir).unwrap();
+	fs::write(dir.join("nvda.exe"), installer()).unwrap();
+	fs::write(dir.join("SHA1SUMS"), format!("{}  nvda.exe\n", sha1(&installer()))).unwrap();
+
+	let output = nvdl_in(&dir, &server, &["verify", "--sums", "SHA1SUMS", "--format", "json"]);
+	assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+	let results: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+	assert_eq!(results[0]["matches"], true);
+
+	fs::write(dir.join("SHA1SUMS"), format!("{}  nvda.exe\n{}  missing.exe\n", sha1(&installer()), "0".repeat(40)))
+		.unwrap();
+	let output = nvdl_in(&dir, &server, &["verify", "--sums", "SHA1SUMS", "--format", "json"]);
+	assert_eq!(output.status.code(), Some(3), "{}", stderr(&output));
+	let error: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+	assert_eq!(error["kind"], "hash_mismatch");
+	assert_eq!(error["error"], "1 of 2 listed files did not match: missing.exe.");
+	assert_eq!(files_in(&dir), ["SHA1SUMS", "nvda.exe"]);
+}
+
+#[test]
+fn locks_and_syncs_installers() {
+	let server = Server::stable(Route::ok(installer()), &sha1(&installer()));
+	let (output, dir) = nvdl(&server, &["lock"]);
+	assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+	let lockfile = fs::read_to_string(dir.join("nvdl.lock")).unwrap();
+	assert!(lockfile.contains(&format!("url = \"{}\"", server.installer_url())), "{lockfile}");
+	assert!(lockfile.contains(&format!("sha1 = \"{}\"", sha1(&installer()))), "{lockfile}");
+	assert!(lockfile.contains("sha256 = "), "{lockfile}");
+
+	let output = nvdl_in(&dir, &server, &["sync", "--locked"]);
+	assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+	assert_eq!(fs::read(dir.join("nvda_2024.4.2.exe")).unwrap(), installer());
+	fs::remove_file(dir.join("nvda_2024.4.2.exe")).unwrap();
+
+	// The installer behind the pinned URL has changed since it was locked.
+	let mut tampered = installer();
+	tampered[100_000] ^= 1;
+	let tampered = Server::stable(Route::ok(tampered), &sha1(&installer()));
+	fs::write(dir.join("nvdl.lock"), lockfile.replace(&server.base, &tampered.base)).unwrap();
+	let output = nvdl_in(&dir, &tampered, &["sync", "--locked"]);
+	assert_eq!(output.status.code(), Some(3), "{}", stderr(&output));
+	assert!(stderr(&output).contains("doesn't match its pinned hash"), "{}", stderr(&output));
+	assert_eq!(files_in(&dir), ["nvdl.lock"]);
+}
+
+#[test]
+fn refuses_lockfile_with_invalid_hash() {
+	let server = Server::stable(Route::ok(installer()), &sha1(&installer()));
+	let dir = std::env::temp_dir().join(format!("nvdl-cli-{}", fastrand::u64(..)));
+	fs::create_dir_all(&dir).unwrap();
+	let lockfile = format!("[[installer]]\ntarget = \"stable\"\nurl = \"{}\"\nsha1 = \"zz\"\n", server.installer_url());
+	fs::write(dir.join("nvdl.lock"), lockfile).unwrap();
+
+	let output = nvdl_in(&dir, &server, &["sync", "--locked", "--format", "json"]);
+	assert_eq!(output.status.code(), Some(4), "{}", stderr(&output));
+	let error: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+	assert_eq!(error["kind"], "invalid_hash");
+	assert_eq!(files_in(&dir), ["nvdl.lock"]);
+}
+
+#[test]
+fn refuses_to_sync_installers_to_the_same_path() {
+	let server = Server::stable(Route::ok(installer()), &sha1(&installer()));
+	let dir = std::env::temp_dir().join(format!("nvdl-cli-{}", fastrand::u64(..)));
+	fs::create_dir_all(&dir).unwrap();
+	let entry = |target: &str, url: &str| {
+		format!("[[installer]]\ntarget = \"{target}\"\nurl = \"{url}\"\nsha1 = \"{}\"\n", sha1(&installer()))
+	};
+	let lockfile = entry("stable", &server.installer_url())
+		+ &entry("beta", &format!("{}/files/nvda_2025.1beta1.exe", server.base));
+	fs::write(dir.join("nvdl.lock"), lockfile).unwrap();
+
+	let output = nvdl_in(&dir, &server, &["sync", "--locked", "-o", "nvda.exe"]);
+	assert_eq!(output.status.code(), Some(2), "{}", stderr(&output));
+	assert!(stderr(&output).contains("More than one installer would be saved as nvda.exe"), "{}", stderr(&output));
+	let output = nvdl_in(&dir, &server, &["sync", "--locked", "-o", "-"]);
+	assert_eq!(output.status.code(), Some(2), "{}", stderr(&output));
+	assert_eq!(files_in(&dir), ["nvdl.lock"]);
+}
+
+#[test]
+fn resumes_interrupted_download() {
+	let route = Route { truncate: Some(50_000), etag: Some("\"v1\""), ..Route::ok(installer()) };
+	let server = Server::stable(route, &sha1(&installer()));
+	let (output, dir) = nvdl(&server, &[]);
+	assert_eq!(output.status.code(), Some(6), "{}", stderr(&output));
+	assert!(stderr(&output).contains("will be resumed next time"), "{}", stderr(&output));
+	assert_eq!(fs::metadata(dir.join("nvda_2024.4.2.exe.part")).unwrap().len(), 50_000);
+
+	let output = nvdl_in(&dir, &server, &[]);
+	assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+	assert!(stderr(&output).contains("Resuming previous download from byte 50000"), "{}", stderr(&output));
+	assert_eq!(fs::read(dir.join("nvda_2024.4.2.exe")).unwrap(), installer());
+	assert_eq!(files_in(&dir), ["nvda_2024.4.2.exe"]);
+}
+
+#[test]
+fn times_out_stalled_download() {
+	let route = Route { truncate: Some(50_000), stall: true, ..Route::ok(installer()) };
+	let server = Server::stable(route, &sha1(&installer()));
+	let start = Instant::now();
+	let (output, dir) = nvdl(&server, &["--read-timeout", "1"]);
+	assert!(start.elapsed() < Duration::from_secs(8), "took {:?}", start.elapsed());
+	assert_eq!(output.status.code(), Some(6), "{}", stderr(&output));
+	assert!(stderr(&output).contains("stopped sending data for 1 second."), "{}", stderr(&output));
+	files_in(&dir);
+}
+
+#[test]
+fn sends_requests_through_proxy() {
+	let server = Server::stable(Route::ok(installer()), &sha1(&installer()));
+	let dir = std::env::temp_dir().join(format!("nvdl-cli-{}", fastrand::u64(..)));
+	fs::create_dir_all(&dir).unwrap();
+	let output = Command::new(env!("CARGO_BIN_EXE_nvdl"))
+		.args(["--api-base", "http://nvda.invalid", "--proxy", &server.base, "--url"])
+		.current_dir(&dir)
+		.env_remove("NO_PROXY")
+		.env_remove("no_proxy")
+		.output()
+		.unwrap();
+	assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+	assert_eq!(stdout(&output), format!("{}\n", server.installer_url()));
+	files_in(&dir);
+}
+
+#[test]
+fn refuses_unusable_ca_cert() {
+	let server = Server::stable(Route::ok(installer()), &sha1(&installer()));
+	let (output, dir) = nvdl(&server, &["--ca-cert", "missing.pem", "--url"]);
+	assert_eq!(output.status.code(), Some(7), "{}", stderr(&output));
+	files_in(&dir);
+
+	let pem = std::env::temp_dir().join(format!("nvdl-cli-{}.pem", fastrand::u64(..)));
+	fs::write(&pem, "not a certificate").unwrap();
+	let (output, dir) = nvdl(&server, &["--ca-cert", pem.to_str().unwrap(), "--url"]);
+	fs::remove_file(&pem).unwrap();
+	assert_eq!(output.status.code(), Some(2), "{}", stderr(&output));
+	assert!(stderr(&output).contains("doesn't contain any PEM certificates"), "{}", stderr(&output));
+	files_in(&dir);
+}
+
+#[test]
+fn rejects_unusable_timeouts() {
+	for timeout in ["inf", "NaN", "-1", "1e30"] {
+		let output =
+			Command::new(env!("CARGO_BIN_EXE_nvdl")).args(["--connect-timeout", timeout, "--url"]).output().unwrap();
+		assert_eq!(output.status.code(), Some(2), "{timeout}: {}", stderr(&output));
+		let output =
+			Command::new(env!("CARGO_BIN_EXE_nvdl")).args(["--read-timeout", timeout, "--url"]).output().unwrap();
+		assert_eq!(output.status.code(), Some(2), "{timeout}: {}", stderr(&output));
+	}
+}
+
+#[test]
+fn warns_when_insecure() {
+	let server = Server::stable(Route::ok(installer()), &sha1(&installer()));
+	let (output, dir) = nvdl(&server, &["--insecure", "--url"]);
+	assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+	assert!(stderr(&output).contains("WARNING: --insecure turns off certificate checks."), "{}", stderr(&output));
+	files_in(&dir);
+}